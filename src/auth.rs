@@ -0,0 +1,175 @@
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use libfj::robocraft::{DefaultTokenProvider, ITokenProvider};
+
+use crate::config::CliArgs;
+use crate::error::{Error, Result};
+
+/// Freejam's account server, which hands out tokens for the factory
+pub const ACCOUNT_DOMAIN: &str = "https://account.freejamgames.com";
+/// Read the password from this environment variable when there's no `--password-file`
+const PASSWORD_VAR: &str = "RCARC_PASSWORD";
+
+/// Who to talk to the factory as
+#[derive(Clone)]
+pub enum Credentials {
+    /// The default (anonymous) user, which can't search for non-buyable robots
+    Anonymous,
+    /// A token obtained elsewhere, which can't be renewed
+    Token(String),
+    /// A Robocraft account, logged in to by display name or email address
+    Account { login: Login, password: String },
+}
+
+#[derive(Clone, Debug)]
+pub enum Login {
+    DisplayName(String),
+    Email(String),
+}
+
+impl Credentials {
+    pub fn from_config(config: &CliArgs) -> Result<Self> {
+        let login = match (&config.username, &config.email) {
+            (Some(_), Some(_)) => return Err(Error::Config("only one of --username and --email can be used".to_owned())),
+            (Some(name), None) => Some(Login::DisplayName(name.to_owned())),
+            (None, Some(email)) => Some(Login::Email(email.to_owned())),
+            (None, None) => None,
+        };
+        match (login, &config.token_file) {
+            (Some(_), Some(_)) => Err(Error::Config("--token-file can't be used with --username or --email".to_owned())),
+            (Some(login), None) => {
+                let password = match &config.password_file {
+                    Some(path) => read_secret(path)?,
+                    None => std::env::var(PASSWORD_VAR)
+                        .map_err(|_| Error::Config(format!("logging in needs --password-file or {}", PASSWORD_VAR)))?,
+                };
+                Ok(Self::Account { login, password })
+            },
+            (None, Some(path)) => Ok(Self::Token(read_secret(path)?)),
+            (None, None) => Ok(Self::Anonymous),
+        }
+    }
+}
+
+/// First line of a file, which must not be empty
+fn read_secret(path: &std::path::Path) -> Result<String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| Error::Config(format!("cannot read {}: {}", path.display(), e)))?;
+    let secret = contents.lines().next().unwrap_or("").trim();
+    if secret.is_empty() {
+        return Err(Error::Config(format!("{} is empty", path.display())));
+    }
+    Ok(secret.to_owned())
+}
+
+/// Provides the token for factory API requests, shared between all clients.
+///
+/// Unlike libfj's `AuthenticatedTokenProvider`, which logs in again for every request,
+/// an account is logged in to once and only again when the factory rejects the token.
+pub struct Session {
+    agent: ureq::Agent,
+    account_url: String,
+    credentials: Credentials,
+    token: Mutex<Option<String>>,
+}
+
+impl Session {
+    pub fn new(agent: ureq::Agent, account_url: &str, credentials: Credentials) -> Self {
+        let token = match &credentials {
+            Credentials::Anonymous => DefaultTokenProvider {}.token().ok(),
+            Credentials::Token(token) => Some(token.to_owned()),
+            Credentials::Account { .. } => None,
+        };
+        Self {
+            agent,
+            account_url: account_url.trim_end_matches('/').to_owned(),
+            credentials,
+            token: Mutex::new(token),
+        }
+    }
+
+    /// Log in now (if there's an account), so that bad credentials are noticed before anything else is done
+    pub fn login(&self) -> Result<()> {
+        if let Credentials::Account { login, .. } = &self.credentials {
+            self.token().map_err(|e| Error::Auth(format!("cannot log in as {}: {}", login, e)))?;
+            tracing::info!("Logged in to the factory as {}", login);
+        }
+        Ok(())
+    }
+
+    /// Token for the next request, logging in if needed
+    pub fn token(&self) -> std::result::Result<String, ureq::Error> {
+        let mut token = self.token.lock().unwrap();
+        if let Some(token) = token.as_ref() {
+            return Ok(token.clone());
+        }
+        let fresh = self.authenticate()?;
+        *token = Some(fresh.clone());
+        Ok(fresh)
+    }
+
+    /// The factory rejected `token`, so log in again for the next request.
+    ///
+    /// Returns false when there's no way to get another token.
+    pub fn renew(&self, rejected: &str) -> bool {
+        if !matches!(self.credentials, Credentials::Account { .. }) {
+            return false;
+        }
+        let mut token = self.token.lock().unwrap();
+        // another client may have already renewed it
+        if token.as_deref() == Some(rejected) {
+            tracing::debug!("Factory rejected the login token, logging in again");
+            *token = None;
+        }
+        true
+    }
+
+    fn authenticate(&self) -> std::result::Result<String, ureq::Error> {
+        let (path, payload) = match &self.credentials {
+            Credentials::Account { login: Login::DisplayName(name), password } => ("/api/authenticate/displayname/web", LoginPayload {
+                display_name: Some(name),
+                email_address: None,
+                password,
+            }),
+            Credentials::Account { login: Login::Email(email), password } => ("/api/authenticate/email/web", LoginPayload {
+                display_name: None,
+                email_address: Some(email),
+                password,
+            }),
+            // these always have a token
+            Credentials::Anonymous | Credentials::Token(_) => unreachable!(),
+        };
+        let response: LoginResponse = self.agent.post(&format!("{}{}", self.account_url, path))
+            .set("Content-Type", "application/json")
+            .send_string(&serde_json::to_string(&payload).unwrap())?
+            .into_json()?;
+        Ok(response.token)
+    }
+}
+
+impl std::fmt::Display for Login {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::DisplayName(name) => write!(f, "{}", name),
+            Self::Email(email) => write!(f, "{}", email),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct LoginPayload<'a> {
+    #[serde(rename = "DisplayName", skip_serializing_if = "Option::is_none")]
+    display_name: Option<&'a str>,
+    #[serde(rename = "EmailAddress", skip_serializing_if = "Option::is_none")]
+    email_address: Option<&'a str>,
+    #[serde(rename = "Password")]
+    password: &'a str,
+}
+
+#[derive(Deserialize)]
+struct LoginResponse {
+    #[serde(rename = "Token")]
+    token: String,
+}
@@ -0,0 +1,222 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use libfj::robocraft::{FactoryInfo, FactoryRobotGetInfo, RoboShopItemsInfo};
+use libfj::robocraft::{FactoryOrderType, FactoryTextSearchType, FACTORY_DOMAIN};
+
+use crate::auth::{Session, ACCOUNT_DOMAIN};
+use crate::config::CliArgs;
+use crate::error::{Error, Result};
+
+const MOVEMENT_FILTER: &str = "100000,200000,300000,400000,500000,600000,700000,800000,900000,1000000,1100000,1200000";
+const WEAPON_FILTER: &str = "10000000,20000000,25000000,30000000,40000000,50000000,60000000,65000000,70100000,75000000";
+
+/// Raw result of a factory API request, before any retrying
+pub type ApiResult<T> = std::result::Result<Fetched<T>, ureq::Error>;
+
+/// A factory API response, along with the JSON it was parsed from.
+///
+/// The JSON is kept because it may contain more than libfj's types do.
+pub struct Fetched<T> {
+    pub info: FactoryInfo<T>,
+    pub raw: serde_json::Value,
+}
+
+/// Creates a factory API client, once for every thread which needs one
+pub type Connect = dyn Fn() -> Box<dyn FactoryClient> + Sync;
+
+/// List query for robots, most recently added first
+#[derive(Clone, Debug)]
+pub struct Search {
+    pub page: isize,
+    pub page_size: isize,
+    /// Only robots uploaded by this player (the factory also matches similar names)
+    pub player: Option<String>,
+    /// Leave out non-buyable robots, which the anonymous default user can't search for anyway
+    pub buyable_only: bool,
+}
+
+impl Search {
+    pub fn all(page: isize, page_size: isize) -> Self {
+        Self {
+            page,
+            page_size,
+            player: None,
+            buyable_only: true,
+        }
+    }
+
+    pub fn by_player(player: &str, page: isize, page_size: isize) -> Self {
+        Self {
+            page,
+            page_size,
+            player: Some(player.to_owned()),
+            buyable_only: true,
+        }
+    }
+
+    /// Include non-buyable robots too, unless `buyable_only`
+    pub fn buyable_only(mut self, buyable_only: bool) -> Self {
+        self.buyable_only = buyable_only;
+        self
+    }
+}
+
+/// The factory API operations which the archiver relies on
+pub trait FactoryClient {
+    /// Search for robots
+    fn list(&self, search: &Search) -> ApiResult<RoboShopItemsInfo>;
+
+    /// Get complete information on a robot, including its cubes
+    fn get(&self, id: usize) -> ApiResult<FactoryRobotGetInfo>;
+}
+
+/// Where and how to send HTTP requests, for both the factory API and thumbnails
+#[derive(Clone, Debug)]
+pub struct HttpSettings {
+    /// Base URL of the factory API
+    pub api_url: String,
+    /// Base URL of the account server, for logging in
+    pub account_url: String,
+    /// Replaces the scheme and host of thumbnail URLs, when set
+    pub thumbnail_host: Option<String>,
+    pub proxy: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl HttpSettings {
+    pub fn from_config(config: &CliArgs) -> Self {
+        Self {
+            api_url: config.api_url.clone().unwrap_or_else(|| FACTORY_DOMAIN.to_owned()),
+            account_url: config.account_url.clone().unwrap_or_else(|| ACCOUNT_DOMAIN.to_owned()),
+            thumbnail_host: config.thumbnail_host.clone(),
+            proxy: config.proxy.clone(),
+            user_agent: config.user_agent.clone(),
+        }
+    }
+
+    /// HTTP agent which uses the proxy and User-Agent (if any)
+    pub fn agent(&self) -> Result<ureq::Agent> {
+        let mut builder = ureq::AgentBuilder::new();
+        if let Some(proxy) = &self.proxy {
+            let proxy = ureq::Proxy::new(proxy)
+                .map_err(|e| Error::Config(format!("invalid proxy `{}`: {}", proxy, e)))?;
+            builder = builder.proxy(proxy);
+        }
+        if let Some(user_agent) = &self.user_agent {
+            builder = builder.user_agent(user_agent);
+        }
+        Ok(builder.build())
+    }
+
+    /// Where to download a thumbnail from
+    pub fn thumbnail_url(&self, url: &str) -> String {
+        let host = match &self.thumbnail_host {
+            Some(host) => host.trim_end_matches('/'),
+            None => return url.to_owned(),
+        };
+        let path = url.split_once("://")
+            .map(|(_, rest)| rest.find('/').map(|i| &rest[i..]).unwrap_or(""))
+            .unwrap_or(url);
+        format!("{}{}", host, path)
+    }
+}
+
+/// Factory API client with a configurable base URL and HTTP agent.
+///
+/// This sends the same requests as libfj's `FactoryAPI`, which is always connected to the production factory.
+pub struct HttpFactory {
+    agent: ureq::Agent,
+    api_url: String,
+    session: Arc<Session>,
+}
+
+impl HttpFactory {
+    pub fn new(agent: ureq::Agent, api_url: &str, session: Arc<Session>) -> Self {
+        Self {
+            agent,
+            api_url: api_url.trim_end_matches('/').to_owned(),
+            session,
+        }
+    }
+
+    /// Send a request (with a JSON `body`, if any), logging in again once if the token is rejected
+    fn send(&self, method: &str, path: &str, body: Option<&str>) -> std::result::Result<ureq::Response, ureq::Error> {
+        let url = format!("{}{}", self.api_url, path);
+        let mut renewed = false;
+        loop {
+            let token = self.session.token()?;
+            let request = self.agent.request(method, &url)
+                .set("Authorization", &format!("Web {}", token));
+            let result = match body {
+                Some(body) => request.set("Content-Type", "application/json").send_string(body),
+                None => request.call(),
+            };
+            match result {
+                Err(ureq::Error::Status(401 | 403, _)) if !renewed && self.session.renew(&token) => renewed = true,
+                result => return result,
+            }
+        }
+    }
+}
+
+impl FactoryClient for HttpFactory {
+    fn list(&self, search: &Search) -> ApiResult<RoboShopItemsInfo> {
+        let payload = ListPayload {
+            page: search.page,
+            page_size: search.page_size,
+            order: FactoryOrderType::Added as isize,
+            player_filter: false,
+            movement_filter: MOVEMENT_FILTER,
+            movement_category_filter: MOVEMENT_FILTER,
+            weapon_filter: WEAPON_FILTER,
+            weapon_category_filter: WEAPON_FILTER,
+            minimum_cpu: -1,
+            maximum_cpu: -1,
+            text_filter: search.player.as_deref().unwrap_or(""),
+            text_search_field: if search.player.is_some() { FactoryTextSearchType::Player as isize } else { 0 },
+            buyable: search.buyable_only,
+            prepend_featured_robot: false,
+            featured_only: false,
+            default_page: false,
+        };
+        let response = self.send("POST", "/api/roboShopItems/list", Some(&serde_json::to_string(&payload).unwrap()))?;
+        parse(response)
+    }
+
+    fn get(&self, id: usize) -> ApiResult<FactoryRobotGetInfo> {
+        let response = self.send("GET", &format!("/api/roboShopItems/get/{}", id), None)?;
+        parse(response)
+    }
+}
+
+/// Body of a list request
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ListPayload<'a> {
+    page: isize,
+    page_size: isize,
+    order: isize,
+    player_filter: bool,
+    movement_filter: &'a str,
+    movement_category_filter: &'a str,
+    weapon_filter: &'a str,
+    weapon_category_filter: &'a str,
+    minimum_cpu: isize,
+    maximum_cpu: isize,
+    text_filter: &'a str,
+    text_search_field: isize,
+    buyable: bool,
+    prepend_featured_robot: bool,
+    featured_only: bool,
+    default_page: bool,
+}
+
+fn parse<T: serde::de::DeserializeOwned>(response: ureq::Response) -> ApiResult<T> {
+    // unlike libfj, which pretends it was a server error (500), malformed data is a transport error
+    let malformed = |e: String| ureq::Error::from(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("malformed response: {}", e)));
+    let raw: serde_json::Value = response.into_json().map_err(|e| malformed(e.to_string()))?;
+    let info = FactoryInfo::<T>::deserialize(&raw).map_err(|e| malformed(e.to_string()))?;
+    Ok(Fetched { info, raw })
+}
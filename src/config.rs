@@ -1,43 +1,618 @@
-use clap::Parser;
+use std::path::{Path, PathBuf};
+
+use clap::{ArgEnum, Args, CommandFactory, ErrorKind, Parser, Subcommand};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+/// Config file used when `--config` isn't given, relative to the working directory
+const LOCAL_CONFIG_FILE: &str = "rcarc.toml";
+/// Config file used when `--config` isn't given and there's no local one, relative to the user's config folder
+const USER_CONFIG_FILE: &str = "rcarc/config.toml";
 
 #[derive(Parser)]
 #[clap(author, version)]
 #[clap(about = "Robocraft CRF archival system")]
 pub struct CliArgs {
     /// Display more messages and more details
-    #[clap(long)]
+    #[clap(long, global = true)]
     pub verbose: bool,
-    
+
+    /// Don't display more messages, even when the config file sets `verbose`
+    #[clap(long, global = true, conflicts_with = "verbose")]
+    pub no_verbose: bool,
+
     /// Path to SQLite database file to use
-    #[clap(long)]
+    #[clap(long, global = true)]
     pub database: Option<String>,
-    
+
+    /// Read options from this TOML file (default: rcarc.toml, or ~/.config/rcarc/config.toml)
+    #[clap(long, global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Print the configuration (config file merged with command line options) and exit
+    #[clap(long, global = true)]
+    pub print_config: bool,
+
+    /// Check which database migrations are pending and try them out, without changing anything or running the command
+    #[clap(long, global = true)]
+    pub migrate_only: bool,
+
+    /// Which messages to log, as a level or per-module filter (e.g. warn,rcarc::thumbnails=debug) (default: info, or debug with --verbose)
+    #[clap(long, global = true)]
+    pub log: Option<String>,
+
+    /// Also log to this file, as JSON lines
+    #[clap(long, global = true)]
+    pub log_file: Option<PathBuf>,
+
+    /// Serve Prometheus metrics over HTTP on this address (e.g. 127.0.0.1:9898)
+    #[clap(long, global = true)]
+    pub metrics_listen: Option<String>,
+
+    /// Write Prometheus metrics to this file regularly, for a textfile collector
+    #[clap(long, global = true)]
+    pub metrics_file: Option<PathBuf>,
+
+    /// Base URL of the factory API, e.g. for a mirror or a caching proxy (default: https://factory.robocraftgame.com/)
+    #[clap(long, global = true)]
+    pub api_url: Option<String>,
+
+    /// Download thumbnails from this host instead, keeping their paths (e.g. http://localhost:8080)
+    #[clap(long, global = true)]
+    pub thumbnail_host: Option<String>,
+
+    /// Send all HTTP requests through this proxy (e.g. http://proxy.example.com:3128)
+    #[clap(long, global = true)]
+    pub proxy: Option<String>,
+
+    /// User-Agent header for all HTTP requests
+    #[clap(long, global = true)]
+    pub user_agent: Option<String>,
+
+    /// Base URL of the account server used to log in (default: https://account.freejamgames.com)
+    #[clap(long, global = true)]
+    pub account_url: Option<String>,
+
+    /// Log in to this Robocraft account by display name, instead of using the factory anonymously
+    #[clap(long, global = true)]
+    pub username: Option<String>,
+
+    /// Log in to this Robocraft account by email address, instead of using the factory anonymously
+    #[clap(long, global = true)]
+    pub email: Option<String>,
+
+    /// Read the account password from this file (default: the RCARC_PASSWORD environment variable)
+    #[clap(long, global = true)]
+    pub password_file: Option<PathBuf>,
+
+    /// Use the factory token in this file, instead of logging in or using the factory anonymously
+    #[clap(long, global = true)]
+    pub token_file: Option<PathBuf>,
+
+    // only optional with --print-config or --migrate-only, which is checked by `parse`
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Download all robots from the robot factory, including those which are not searchable, starting with the most recent
+    Sweep(SweepArgs),
+    /// Download all searchable robots
+    Scrape(FetchArgs),
+    /// Download all newly-uploaded robots, as found by searching by newly-added robots
+    New(NewArgs),
+    /// Download specific robots, listed on the command line, in a file or on stdin
+    Fetch(FetchIdsArgs),
+    /// Download all searchable robots uploaded by a player
+    Author(AuthorArgs),
+    /// Re-check all stored robots, recording which ones are no longer available
+    Revalidate(FetchArgs),
+    /// Re-download all thumbnails
+    Thumbs(ThumbsArgs),
+    /// Show how a robot's metadata (popularity, ratings, etc.) changed over time
+    History(HistoryArgs),
+    /// Write stored robots to a JSON lines file
+    Export(ExportArgs),
+    /// Find stored robots by name, description or author
+    Search(SearchArgs),
+    /// Show a summary of what's in the database
+    Stats,
+    /// Check the database for corruption and inconsistencies
+    Verify,
+    /// Show (or reset) where each of sweep, new and scrape will resume
+    State(StateArgs),
+    /// Rebuild robot metadata and cubes from the stored raw API responses
+    Rederive,
+}
+
+/// Commands which keep their own resumable state
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Sweep,
+    New,
+    Scrape,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 3] = [Mode::Sweep, Mode::New, Mode::Scrape];
+
+    /// ID of this mode's row in STATE
+    pub fn state_id(self) -> usize {
+        match self {
+            Self::Sweep => 0,
+            Self::New => 1,
+            Self::Scrape => 2,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Sweep => "sweep",
+            Self::New => "new",
+            Self::Scrape => "scrape",
+        }
+    }
+}
+
+/// Options for commands which talk to the factory API
+#[derive(Args)]
+pub struct FetchArgs {
     /// Robots per page
     #[clap(short, long)]
     pub size: Option<isize>,
 
-    /// Only look for new robots
+    /// Download thumbnails to this folder (default: don't download thumbnails)
     #[clap(short, long)]
-    pub new: bool,
+    pub thumbnails: Option<PathBuf>,
 
-    /// Download known robots
+    /// Number of robots to retrieve concurrently (default: 1)
     #[clap(short, long)]
-    pub known: bool,
+    pub workers: Option<usize>,
 
-    /// Download thumbnails to this folder (default: don't download thumbnails)
+    /// Maximum attempts for each factory API request (default: 5)
+    #[clap(long)]
+    pub attempts: Option<usize>,
+
+    /// Initial delay between factory API request attempts, in milliseconds (default: 500)
+    #[clap(long)]
+    pub retry_delay: Option<u64>,
+
+    /// Maximum factory API requests per second (default: unlimited)
+    #[clap(long)]
+    pub api_rate: Option<f64>,
+
+    /// Maximum thumbnail downloads per second (default: unlimited)
+    #[clap(long)]
+    pub thumbnail_rate: Option<f64>,
+
+    /// Maximum factory API requests per (UTC) day, counting earlier runs, stopping once it's used up (default: unlimited)
+    #[clap(long)]
+    pub daily_budget: Option<usize>,
+}
+
+/// Used by commands which don't talk to the factory API
+const NO_FETCH_ARGS: FetchArgs = FetchArgs {
+    size: None,
+    thumbnails: None,
+    workers: None,
+    attempts: None,
+    retry_delay: None,
+    api_rate: None,
+    thumbnail_rate: None,
+    daily_budget: None,
+};
+
+#[derive(Args)]
+pub struct SweepArgs {
+    #[clap(flatten)]
+    pub fetch: FetchArgs,
+
+    /// Brute-force robot IDs starting from this ID, instead of resuming the full sweep (default: most recent robot in the database)
+    #[clap(long)]
+    pub from_id: Option<usize>,
+
+    /// Brute-force robot IDs down (or up) to this ID, instead of resuming the full sweep (default: 0)
+    #[clap(long)]
+    pub to_id: Option<usize>,
+
+    /// Retry robot IDs which previously responded with 404 or 410 (other failures are always retried)
+    #[clap(long)]
+    pub recheck_missing: bool,
+}
+
+#[derive(Args)]
+pub struct NewArgs {
+    #[clap(flatten)]
+    pub fetch: FetchArgs,
+
+    /// Keep looking for new robots, waiting this long between searches (e.g. 90s, 30m, 6h)
+    #[clap(long, parse(try_from_str = parse_interval))]
+    pub watch: Option<std::time::Duration>,
+
+    /// Retry robot IDs which previously responded with 404 or 410 (other failures are always retried)
+    #[clap(long)]
+    pub recheck_missing: bool,
+}
+
+#[derive(Args)]
+pub struct FetchIdsArgs {
+    #[clap(flatten)]
+    pub fetch: FetchArgs,
+
+    /// Robots to download (e.g. 1234,5678)
+    #[clap(use_value_delimiter = true, required_unless_present = "id-file")]
+    pub ids: Vec<usize>,
+
+    /// Download the robots listed in this file, separated by whitespace or commas (- for stdin)
+    #[clap(long)]
+    pub id_file: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct AuthorArgs {
+    #[clap(flatten)]
+    pub fetch: FetchArgs,
+
+    /// Player whose robots will be downloaded
+    pub name: String,
+}
+
+#[derive(Args)]
+pub struct ThumbsArgs {
+    /// Download thumbnails to this folder
+    pub folder: PathBuf,
+
+    /// Maximum thumbnail downloads per second (default: unlimited)
+    #[clap(long)]
+    pub thumbnail_rate: Option<f64>,
+}
+
+#[derive(Args)]
+pub struct HistoryArgs {
+    /// Robot ID
+    pub id: usize,
+}
+
+#[derive(Args)]
+pub struct StateArgs {
+    /// Forget where this mode got to, so its next run starts from the beginning
+    #[clap(long, arg_enum)]
+    pub reset: Option<Mode>,
+}
+
+#[derive(Args)]
+pub struct ExportArgs {
+    /// Write to this file instead of stdout
     #[clap(short, long)]
-    pub thumbnails: Option<std::path::PathBuf>,
+    pub output: Option<PathBuf>,
 
-    /// Re-download all thumbnails
+    /// Include robot cubes data
     #[clap(long)]
-    pub rethumb: bool,
+    pub cubes: bool,
+
+    /// Only export robots which are still available from the factory
+    #[clap(long)]
+    pub live_only: bool,
+
+    #[clap(flatten)]
+    pub dates: DateRange,
 }
 
-pub fn parse() -> CliArgs {
-    let args = CliArgs::parse();
-    if args.rethumb && args.thumbnails.is_none() {
-        eprintln!("Missing required thumbnails folder in CLI args");
-        panic!("How do I re-download all thumbnails without a thumbnails folder to put them in?");
+#[derive(Args)]
+pub struct SearchArgs {
+    /// Words to look for, in SQLite full-text query syntax (e.g. `tank`, `"hover tank"`, `jet OR plane`, `name:tank`)
+    pub query: String,
+
+    /// Show at most this many robots
+    #[clap(long, default_value = "20")]
+    pub limit: usize,
+
+    /// Folder where thumbnails are downloaded to (default: the working directory)
+    #[clap(long)]
+    pub thumbnails: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub dates: DateRange,
+}
+
+/// Limits on when robots were added to the factory and when they expire.
+///
+/// Dates are UTC, given as YYYY-MM-DD, YYYY-MM-DDTHH:MM[:SS] or seconds since the unix epoch.
+#[derive(Args, Default)]
+pub struct DateRange {
+    /// Only robots added at or after this date
+    #[clap(long, parse(try_from_str = parse_date))]
+    pub added_after: Option<i64>,
+
+    /// Only robots added before this date
+    #[clap(long, parse(try_from_str = parse_date))]
+    pub added_before: Option<i64>,
+
+    /// Only robots expiring at or after this date
+    #[clap(long, parse(try_from_str = parse_date))]
+    pub expires_after: Option<i64>,
+
+    /// Only robots expiring before this date
+    #[clap(long, parse(try_from_str = parse_date))]
+    pub expires_before: Option<i64>,
+}
+
+impl DateRange {
+    /// SQL conditions on ROBOT_METADATA (as `rm`) to AND with a query, and their parameters
+    pub fn conditions(&self) -> (Vec<&'static str>, Vec<i64>) {
+        let limits = [
+            ("rm.added_at >= ?", self.added_after),
+            ("rm.added_at < ?", self.added_before),
+            ("rm.expires_at >= ?", self.expires_after),
+            ("rm.expires_at < ?", self.expires_before),
+        ];
+        limits.into_iter()
+            .filter_map(|(condition, limit)| Some((condition, limit?)))
+            .unzip()
+    }
+}
+
+/// Options which can be set in a config file.
+///
+/// Every one of these is overridden by the equivalent command line option.
+#[derive(Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileConfig {
+    pub verbose: Option<bool>,
+    pub database: Option<String>,
+    pub log: Option<String>,
+    pub log_file: Option<PathBuf>,
+    pub metrics_listen: Option<String>,
+    pub metrics_file: Option<PathBuf>,
+    pub api_url: Option<String>,
+    pub thumbnail_host: Option<String>,
+    pub proxy: Option<String>,
+    pub user_agent: Option<String>,
+    pub account_url: Option<String>,
+    pub username: Option<String>,
+    pub email: Option<String>,
+    pub password_file: Option<PathBuf>,
+    pub token_file: Option<PathBuf>,
+    pub size: Option<isize>,
+    pub thumbnails: Option<PathBuf>,
+    pub workers: Option<usize>,
+    pub attempts: Option<usize>,
+    pub retry_delay: Option<u64>,
+    pub api_rate: Option<f64>,
+    pub thumbnail_rate: Option<f64>,
+    pub daily_budget: Option<usize>,
+}
+
+impl FileConfig {
+    fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| Error::Config(format!("cannot read config file {}: {}", path.display(), e)))?;
+        toml::from_str(&text)
+            .map_err(|e| Error::Config(format!("invalid config file {}: {}", path.display(), e)))
+    }
+
+    /// Find the config file to use, if any
+    fn locate(explicit: Option<&Path>) -> Option<PathBuf> {
+        if let Some(path) = explicit {
+            return Some(path.to_owned());
+        }
+        let local = PathBuf::from(LOCAL_CONFIG_FILE);
+        if local.is_file() {
+            return Some(local);
+        }
+        let user_dir = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(user_dir.join(USER_CONFIG_FILE)).filter(|path| path.is_file())
+    }
+}
+
+impl CliArgs {
+    /// Factory API options for the current command
+    pub fn fetch(&self) -> &FetchArgs {
+        match &self.command {
+            Some(Command::Sweep(args)) => &args.fetch,
+            Some(Command::Scrape(args)) => args,
+            Some(Command::New(args)) => &args.fetch,
+            Some(Command::Fetch(args)) => &args.fetch,
+            Some(Command::Author(args)) => &args.fetch,
+            Some(Command::Revalidate(args)) => args,
+            _ => &NO_FETCH_ARGS,
+        }
+    }
+
+    fn fetch_mut(&mut self) -> Option<&mut FetchArgs> {
+        match &mut self.command {
+            Some(Command::Sweep(args)) => Some(&mut args.fetch),
+            Some(Command::Scrape(args)) => Some(args),
+            Some(Command::New(args)) => Some(&mut args.fetch),
+            Some(Command::Fetch(args)) => Some(&mut args.fetch),
+            Some(Command::Author(args)) => Some(&mut args.fetch),
+            Some(Command::Revalidate(args)) => Some(args),
+            _ => None,
+        }
+    }
+
+    /// Fill in options which weren't given on the command line from the config file
+    fn merge(&mut self, file: FileConfig) {
+        self.verbose = !self.no_verbose && (self.verbose || file.verbose.unwrap_or(false));
+        self.database = self.database.take().or(file.database);
+        self.log = self.log.take().or(file.log);
+        self.log_file = self.log_file.take().or(file.log_file);
+        self.metrics_listen = self.metrics_listen.take().or(file.metrics_listen);
+        self.metrics_file = self.metrics_file.take().or(file.metrics_file);
+        self.api_url = self.api_url.take().or(file.api_url);
+        self.thumbnail_host = self.thumbnail_host.take().or(file.thumbnail_host);
+        self.proxy = self.proxy.take().or(file.proxy);
+        self.user_agent = self.user_agent.take().or(file.user_agent);
+        self.account_url = self.account_url.take().or(file.account_url);
+        self.username = self.username.take().or(file.username);
+        self.email = self.email.take().or(file.email);
+        self.password_file = self.password_file.take().or(file.password_file);
+        self.token_file = self.token_file.take().or(file.token_file);
+        if let Some(Command::Thumbs(args)) = &mut self.command {
+            args.thumbnail_rate = args.thumbnail_rate.or(file.thumbnail_rate);
+        }
+        if let Some(fetch) = self.fetch_mut() {
+            fetch.size = fetch.size.or(file.size);
+            fetch.thumbnails = fetch.thumbnails.take().or(file.thumbnails);
+            fetch.workers = fetch.workers.or(file.workers);
+            fetch.attempts = fetch.attempts.or(file.attempts);
+            fetch.retry_delay = fetch.retry_delay.or(file.retry_delay);
+            fetch.api_rate = fetch.api_rate.or(file.api_rate);
+            fetch.thumbnail_rate = fetch.thumbnail_rate.or(file.thumbnail_rate);
+            fetch.daily_budget = fetch.daily_budget.or(file.daily_budget);
+        }
+    }
+
+    /// The options in effect for the current command, in config file format
+    pub fn effective(&self) -> FileConfig {
+        let fetch = self.fetch();
+        FileConfig {
+            verbose: Some(self.verbose),
+            database: self.database.clone(),
+            log: self.log.clone(),
+            log_file: self.log_file.clone(),
+            metrics_listen: self.metrics_listen.clone(),
+            metrics_file: self.metrics_file.clone(),
+            api_url: self.api_url.clone(),
+            thumbnail_host: self.thumbnail_host.clone(),
+            proxy: self.proxy.clone(),
+            user_agent: self.user_agent.clone(),
+            account_url: self.account_url.clone(),
+            username: self.username.clone(),
+            email: self.email.clone(),
+            password_file: self.password_file.clone(),
+            token_file: self.token_file.clone(),
+            size: fetch.size,
+            thumbnails: fetch.thumbnails.clone(),
+            workers: fetch.workers,
+            attempts: fetch.attempts,
+            retry_delay: fetch.retry_delay,
+            api_rate: fetch.api_rate,
+            thumbnail_rate: match &self.command {
+                Some(Command::Thumbs(args)) => args.thumbnail_rate,
+                _ => fetch.thumbnail_rate,
+            },
+            daily_budget: fetch.daily_budget,
+        }
+    }
+
+    /// The options in effect for the current command, as TOML
+    pub fn effective_toml(&self) -> Result<String> {
+        toml::to_string(&self.effective())
+            .map_err(|e| Error::Config(format!("cannot serialize configuration: {}", e)))
+    }
+
+    /// Resumable mode of the current command, if it has one
+    pub fn mode(&self) -> Option<Mode> {
+        match &self.command {
+            Some(Command::Sweep(args)) if args.from_id.is_none() && args.to_id.is_none() => Some(Mode::Sweep),
+            Some(Command::New(_)) => Some(Mode::New),
+            Some(Command::Scrape(_)) => Some(Mode::Scrape),
+            _ => None,
+        }
+    }
+
+    /// Using an account or a token, rather than the anonymous default user?
+    pub fn logged_in(&self) -> bool {
+        self.username.is_some() || self.email.is_some() || self.token_file.is_some()
+    }
+
+    /// Does the current command talk to the factory API?
+    pub fn uses_api(&self) -> bool {
+        matches!(self.command, Some(Command::Sweep(_) | Command::Scrape(_) | Command::New(_) | Command::Fetch(_) | Command::Author(_) | Command::Revalidate(_)))
+    }
+
+    /// Only looking for new robots?
+    pub fn new_only(&self) -> bool {
+        matches!(self.command, Some(Command::New(_)))
+    }
+
+    /// Retry robot IDs which are known to be missing?
+    pub fn recheck_missing(&self) -> bool {
+        match &self.command {
+            Some(Command::Sweep(args)) => args.recheck_missing,
+            Some(Command::New(args)) => args.recheck_missing,
+            _ => false,
+        }
+    }
+}
+
+pub fn parse() -> Result<CliArgs> {
+    let mut args = CliArgs::parse();
+    if args.command.is_none() && !args.print_config && !args.migrate_only {
+        CliArgs::command()
+            .error(ErrorKind::MissingSubcommand, "a command is required, unless using --print-config or --migrate-only")
+            .exit();
+    }
+    if let Some(path) = FileConfig::locate(args.config.as_deref()) {
+        args.merge(FileConfig::load(&path)?);
+    }
+    if args.fetch().workers == Some(0) {
+        return Err(Error::Config("--workers must be at least 1".to_owned()));
+    }
+    Ok(args)
+}
+
+/// Parse robot IDs separated by whitespace or commas
+pub fn parse_id_list(s: &str) -> Result<Vec<usize>> {
+    s.split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|id| !id.is_empty())
+        .map(|id| id.parse::<usize>().map_err(|e| Error::Config(format!("invalid robot ID `{}`: {}", id, e))))
+        .collect()
+}
+
+fn parse_interval(s: &str) -> std::result::Result<std::time::Duration, String> {
+    let (number, multiplier) = match s.char_indices().last() {
+        Some((i, 's')) => (&s[..i], 1),
+        Some((i, 'm')) => (&s[..i], 60),
+        Some((i, 'h')) => (&s[..i], 60 * 60),
+        Some((i, 'd')) => (&s[..i], 24 * 60 * 60),
+        _ => (s, 1),
+    };
+    let seconds = number.parse::<u64>()
+        .map_err(|e| format!("invalid interval `{}`: {}", s, e))?
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("interval `{}` is too long", s))?;
+    if seconds == 0 {
+        return Err("interval must be at least 1s".to_owned());
+    }
+    Ok(std::time::Duration::from_secs(seconds))
+}
+
+/// Seconds since the unix epoch of a UTC date, with an optional time
+fn parse_date(s: &str) -> std::result::Result<i64, String> {
+    if let Ok(epoch) = s.parse::<i64>() {
+        return Ok(epoch);
+    }
+    let invalid = || format!("invalid date `{}`, expected YYYY-MM-DD or YYYY-MM-DDTHH:MM[:SS]", s);
+    let trimmed = s.strip_suffix('Z').unwrap_or(s);
+    let (date, time) = trimmed.split_once(['T', ' ']).unwrap_or((trimmed, "00:00"));
+    let numbers = |part: &str, sep: char| part.split(sep)
+        .map(|n| n.parse::<i64>())
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|_| invalid());
+    let (year, month, day) = match numbers(date, '-')?[..] {
+        [year, month, day] if (1..=12).contains(&month) && (1..=31).contains(&day) => (year, month, day),
+        _ => return Err(invalid()),
+    };
+    let (hour, minute, second) = match numbers(time, ':')?[..] {
+        [hour, minute] => (hour, minute, 0),
+        [hour, minute, second] => (hour, minute, second),
+        _ => return Err(invalid()),
+    };
+    if !(0..24).contains(&hour) || !(0..60).contains(&minute) || !(0..60).contains(&second) {
+        return Err(invalid());
     }
-    args
+    // days since 1970-01-01 in the proleptic Gregorian calendar, counting years from March so leap days come last
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+    Ok(days * 86400 + hour * 3600 + minute * 60 + second)
 }
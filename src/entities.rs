@@ -1,42 +1,10 @@
 use libfj::robocraft::{FactoryRobotListInfo, FactoryRobotGetInfo};
 use std::convert::From;
 
-pub fn build_database(db: &mut rusqlite::Connection) -> rusqlite::Result<()> {
-    db.execute_batch(
-    "BEGIN;
-    CREATE TABLE IF NOT EXISTS ROBOT_METADATA (
-        id INTEGER NOT NULL PRIMARY KEY,
-        name TEXT NOT NULL,
-        description TEXT NOT NULL,
-        thumbnail TEXT NOT NULL,
-        added_by TEXT NOT NULL,
-        added_by_display_name TEXT NOT NULL,
-        added_date TEXT NOT NULL,
-        expiry_date TEXT NOT NULL,
-        cpu INTEGER NOT NULL,
-        total_robot_ranking INTEGER NOT NULL,
-        rent_count INTEGER NOT NULL,
-        buy_count INTEGER NOT NULL,
-        buyable INTEGER NOT NULL,
-        featured INTEGER NOT NULL,
-        combat_rating REAL NOT NULL,
-        cosmetic_rating REAL NOT NULL
-    );
-    CREATE TABLE IF NOT EXISTS ROBOT_CUBES (
-        id INTEGER NOT NULL PRIMARY KEY,
-        cube_data TEXT NOT NULL,
-        colour_data TEXT NOT NULL,
-        cube_amounts TEXT NOT NULL
-    );
-    CREATE TABLE IF NOT EXISTS STATE (
-        id INTEGER NOT NULL PRIMARY KEY,
-        next_page INTEGER NOT NULL,
-        last_page_size INTEGER NOT NULL,
-        last_sequential_id INTEGER NOT NULL
-    );
-    COMMIT;"
-    )
-}
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use serde::Serialize;
 
 pub trait Entity: Sized {
     fn map_row(row: &rusqlite::Row) -> rusqlite::Result<Self>;
@@ -47,7 +15,7 @@ pub trait Entity: Sized {
     fn id(&self) -> usize;
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub struct DbMetaData {
     pub id: usize,
     pub name: String,
@@ -85,6 +53,9 @@ impl Entity for DbMetaData {
     featured INTEGER NOT NULL,
     combat_rating REAL NOT NULL,
     cosmetic_rating REAL NOT NULL,
+    -- added_date and expiry_date in seconds since the unix epoch (UTC), derived when saved
+    added_at INTEGER,
+    expires_at INTEGER,
     */
     fn map_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
         Ok(Self {
@@ -179,6 +150,36 @@ impl From<FactoryRobotGetInfo> for DbMetaData {
     }
 }
 
+/// Metadata as it was at some point in time
+#[derive(Clone, Debug)]
+pub struct DbMetaDataSnapshot {
+    pub metadata: DbMetaData,
+    pub observed_at: u64,
+}
+
+impl Entity for DbMetaDataSnapshot {
+    /*
+    (same as ROBOT_METADATA)
+    observed_at INTEGER NOT NULL,
+    */
+    fn map_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            metadata: DbMetaData::map_row(row)?,
+            observed_at: row.get(16)?,
+        })
+    }
+
+    fn to_params(&self) -> Vec<&dyn rusqlite::ToSql> {
+        let mut params = self.metadata.to_params();
+        params.push(&self.observed_at);
+        params
+    }
+
+    fn id(&self) -> usize {
+        self.metadata.id
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct DbCubeData {
     pub id: usize,
@@ -236,6 +237,17 @@ pub struct DbState {
     pub last_sequential_id: usize,
 }
 
+impl DbState {
+    /// State which hasn't made any progress yet
+    pub fn fresh(id: usize, page_size: isize) -> Self {
+        Self {
+            id,
+            next_page: 0,
+            last_page_size: page_size,
+            last_sequential_id: u32::MAX as _,
+        }
+    }
+}
 
 impl Entity for DbState {
     /*
@@ -266,3 +278,177 @@ impl Entity for DbState {
         self.id
     }
 }
+
+#[derive(Clone, Debug)]
+pub struct DbMissing {
+    pub id: usize,
+    pub status_code: usize,
+    pub attempts: usize,
+    pub first_checked: u64,
+    pub last_checked: u64,
+}
+
+impl DbMissing {
+    /// A robot which has just failed to be retrieved for the first time
+    pub fn new(id: usize, status_code: usize) -> Self {
+        let now = unix_now();
+        Self {
+            id,
+            status_code,
+            attempts: 1,
+            first_checked: now,
+            last_checked: now,
+        }
+    }
+}
+
+impl Entity for DbMissing {
+    /*
+    id INTEGER NOT NULL PRIMARY KEY,
+    status_code INTEGER NOT NULL,
+    attempts INTEGER NOT NULL,
+    first_checked INTEGER NOT NULL,
+    last_checked INTEGER NOT NULL,
+    */
+    fn map_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            status_code: row.get(1)?,
+            attempts: row.get(2)?,
+            first_checked: row.get(3)?,
+            last_checked: row.get(4)?,
+        })
+    }
+
+    fn to_params(&self) -> Vec<&dyn rusqlite::ToSql> {
+        vec![
+            &self.id,
+            &self.status_code,
+            &self.attempts,
+            &self.first_checked,
+            &self.last_checked,
+        ]
+    }
+
+    fn id(&self) -> usize {
+        self.id
+    }
+}
+
+/// A stored robot which is no longer available from the factory
+#[derive(Clone, Debug)]
+pub struct DbTombstone {
+    pub id: usize,
+    pub status_code: usize,
+    pub first_unavailable: u64,
+    pub last_checked: u64,
+}
+
+impl DbTombstone {
+    /// A robot which has just been found to be unavailable
+    pub fn new(id: usize, status_code: usize) -> Self {
+        let now = unix_now();
+        Self {
+            id,
+            status_code,
+            first_unavailable: now,
+            last_checked: now,
+        }
+    }
+}
+
+impl Entity for DbTombstone {
+    /*
+    id INTEGER NOT NULL PRIMARY KEY,
+    status_code INTEGER NOT NULL,
+    first_unavailable INTEGER NOT NULL,
+    last_checked INTEGER NOT NULL,
+    */
+    fn map_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            status_code: row.get(1)?,
+            first_unavailable: row.get(2)?,
+            last_checked: row.get(3)?,
+        })
+    }
+
+    fn to_params(&self) -> Vec<&dyn rusqlite::ToSql> {
+        vec![
+            &self.id,
+            &self.status_code,
+            &self.first_unavailable,
+            &self.last_checked,
+        ]
+    }
+
+    fn id(&self) -> usize {
+        self.id
+    }
+}
+
+/// A robot's JSON, with every field the factory sent, from a list (`source` = "list") or get ("get") response
+#[derive(Clone, Debug)]
+pub struct DbRawResponse {
+    pub id: usize,
+    pub fetched_at: u64,
+    pub source: String,
+    /// zlib-compressed JSON
+    pub payload: Vec<u8>,
+}
+
+impl DbRawResponse {
+    pub fn new(id: usize, fetched_at: u64, source: &str, json: &serde_json::Value) -> std::io::Result<Self> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        serde_json::to_writer(&mut encoder, json)?;
+        Ok(Self {
+            id,
+            fetched_at,
+            source: source.to_owned(),
+            payload: encoder.finish()?,
+        })
+    }
+
+    /// Decompress and parse the JSON
+    pub fn json(&self) -> std::io::Result<serde_json::Value> {
+        Ok(serde_json::from_reader(ZlibDecoder::new(self.payload.as_slice()))?)
+    }
+}
+
+impl Entity for DbRawResponse {
+    /*
+    id INTEGER NOT NULL,
+    fetched_at INTEGER NOT NULL,
+    source TEXT NOT NULL,
+    payload BLOB NOT NULL,
+    */
+    fn map_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            fetched_at: row.get(1)?,
+            source: row.get(2)?,
+            payload: row.get(3)?,
+        })
+    }
+
+    fn to_params(&self) -> Vec<&dyn rusqlite::ToSql> {
+        vec![
+            &self.id,
+            &self.fetched_at,
+            &self.source,
+            &self.payload,
+        ]
+    }
+
+    fn id(&self) -> usize {
+        self.id
+    }
+}
+
+/// Current time, in seconds since the unix epoch
+pub fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
@@ -0,0 +1,94 @@
+use std::fmt;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Everything that can go wrong while archiving.
+///
+/// Each kind of failure exits the process with its own code (see `exit_code()`),
+/// so scripts can tell an unreachable API apart from a broken database.
+#[derive(Debug)]
+pub enum Error {
+    /// Invalid command line arguments (exit code 2)
+    Config(String),
+    /// SQLite failure (exit code 3)
+    Database(rusqlite::Error),
+    /// Factory API could not be reached, or returned garbage (exit code 4)
+    Api(Box<ureq::Error>),
+    /// Factory API responded with an unsuccessful status code (exit code 5)
+    Status(usize),
+    /// Filesystem failure (exit code 6)
+    Io(std::io::Error),
+    /// Database is corrupt, or missing some robot data (exit code 7)
+    Inconsistent(String),
+    /// Logging in to the account failed (exit code 8)
+    Auth(String),
+    /// Database schema is newer than this build supports (exit code 9)
+    NewerSchema { found: usize, supported: usize },
+    /// The daily request budget is used up, so the request was never sent (exit code 0)
+    BudgetExhausted,
+}
+
+impl Error {
+    /// Process exit code for this kind of failure
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Config(_) => 2,
+            Self::Database(_) => 3,
+            Self::Api(_) => 4,
+            Self::Status(_) => 5,
+            Self::Io(_) => 6,
+            Self::Inconsistent(_) => 7,
+            Self::Auth(_) => 8,
+            Self::NewerSchema { .. } => 9,
+            Self::BudgetExhausted => 0,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Config(msg) => write!(f, "Invalid configuration: {}", msg),
+            Self::Database(e) => write!(f, "Database error: {}", e),
+            Self::Api(e) => write!(f, "Factory API error: {}", e),
+            Self::Status(code) => write!(f, "Factory API responded with status {}", code),
+            Self::Io(e) => write!(f, "IO error: {}", e),
+            Self::Inconsistent(msg) => write!(f, "Database check failed: {}", msg),
+            Self::Auth(msg) => write!(f, "Login failed: {}", msg),
+            Self::NewerSchema { found, supported } => write!(f, "Database schema version {} is newer than this rcarc supports ({}), please upgrade rcarc", found, supported),
+            Self::BudgetExhausted => write!(f, "Daily request budget used up"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Database(e) => Some(e),
+            Self::Api(e) => Some(e.as_ref()),
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+        Self::Database(e)
+    }
+}
+
+impl From<ureq::Error> for Error {
+    fn from(e: ureq::Error) -> Self {
+        match e {
+            ureq::Error::Status(code, _) => Self::Status(code as usize),
+            e => Self::Api(Box::new(e)),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::BTreeMap;
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use libfj::robocraft::FactoryRobotGetInfo;
+
+use tracing::Span;
+
+use crate::client::{Connect, Fetched};
+use crate::error::Result;
+use crate::retry::RetryPolicy;
+
+pub type GetResult = Result<Fetched<FactoryRobotGetInfo>>;
+
+/// How many robots past the lowest unhandled one can be retrieved, per worker.
+/// This bounds how many results wait to be re-ordered when one robot takes a long time (e.g. while it's retried).
+const WINDOW_PER_WORKER: usize = 4;
+
+/// Retrieve robots by ID using a pool of `workers` threads, each with its own client from `connect`.
+///
+/// Results are handed to `handler` in the same order as `ids`, even though they may be retrieved out of order,
+/// so the handler can treat every ID it receives as the lowest (or highest) one which has been completed.
+/// Only a few robots per worker past the lowest one which hasn't been handled are retrieved at a time.
+/// Every request is retried according to `retry`.
+/// Retrieval stops early when `handler` returns false or an error, and that error is returned.
+/// Retrieving and handling each robot happens in a `robot` span, tagged with `op`.
+pub fn fetch_robots<I, F>(ids: I, workers: usize, connect: &Connect, retry: &RetryPolicy, op: &'static str, mut handler: F) -> Result<()>
+where
+    I: Iterator<Item = usize> + Send,
+    F: FnMut(usize, GetResult) -> Result<bool>,
+{
+    let workers = workers.max(1);
+    let window = workers * WINDOW_PER_WORKER;
+    let stop = AtomicBool::new(false);
+    // sequence number of the next result to be handled, which the dispatcher waits on
+    let handled = (Mutex::new(0), Condvar::new());
+    let (id_tx, id_rx) = mpsc::sync_channel::<(usize, usize)>(workers);
+    let id_rx = Arc::new(Mutex::new(id_rx));
+    let (result_tx, result_rx) = mpsc::sync_channel::<(usize, usize, Span, GetResult)>(workers);
+    let parent = Span::current();
+    std::thread::scope(|scope| {
+        let stop = &stop;
+        let handled = &handled;
+        // dispatcher
+        scope.spawn(move || {
+            for (seq, id) in ids.enumerate() {
+                let (next_seq, advanced) = handled;
+                let mut next_seq = next_seq.lock().unwrap();
+                while seq >= *next_seq + window && !stop.load(Ordering::Relaxed) {
+                    next_seq = advanced.wait(next_seq).unwrap();
+                }
+                drop(next_seq);
+                if stop.load(Ordering::Relaxed) || id_tx.send((seq, id)).is_err() {
+                    break;
+                }
+            }
+        });
+        // retrievers
+        for _ in 0..workers {
+            let id_rx = id_rx.clone();
+            let result_tx = result_tx.clone();
+            let parent = &parent;
+            scope.spawn(move || {
+                let api = connect();
+                loop {
+                    let next = id_rx.lock().unwrap().recv();
+                    let (seq, id) = match next {
+                        Ok(x) => x,
+                        Err(_) => break,
+                    };
+                    if stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let span = tracing::info_span!(parent: parent, "robot", op, robot_id = id);
+                    let result = span.in_scope(|| retry.run(&format!("robot #{}", id), || api.get(id)));
+                    if result_tx.send((seq, id, span, result)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(id_rx);
+        drop(result_tx);
+        // re-order results so they're handled sequentially
+        let mut next_seq = 0;
+        let mut pending = BTreeMap::new();
+        let mut outcome = Ok(());
+        'results: for (seq, id, span, result) in result_rx.iter() {
+            pending.insert(seq, (id, span, result));
+            while let Some((id, span, result)) = pending.remove(&next_seq) {
+                next_seq += 1;
+                *handled.0.lock().unwrap() = next_seq;
+                handled.1.notify_one();
+                match span.in_scope(|| handler(id, result)) {
+                    Ok(true) => {},
+                    Ok(false) => break 'results,
+                    Err(e) => {
+                        outcome = Err(e);
+                        break 'results;
+                    }
+                }
+            }
+        }
+        {
+            // while holding the lock, so the dispatcher can't miss it
+            let _next_seq = handled.0.lock().unwrap();
+            stop.store(true, Ordering::Relaxed);
+        }
+        handled.1.notify_one();
+        // unblock any retrievers which are still running
+        drop(result_rx);
+        outcome
+    })
+}
@@ -0,0 +1,226 @@
+use std::io::Write;
+
+use rusqlite::Connection;
+use serde::Serialize;
+
+use crate::config::{ExportArgs, Mode, SearchArgs, StateArgs};
+use crate::entities::{Entity, DbMetaData, DbMetaDataSnapshot, DbState};
+use crate::error::{Error, Result};
+
+/// One line of `export` output
+#[derive(Serialize)]
+struct ExportRecord {
+    #[serde(flatten)]
+    metadata: DbMetaData,
+    added_at: Option<i64>,
+    expires_at: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cube_data: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    colour_data: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cube_amounts: Option<String>,
+}
+
+pub fn print_history(db: &mut Connection, id: usize) -> Result<()> {
+    let snapshots: Vec<DbMetaDataSnapshot> = db
+        .prepare("SELECT * FROM ROBOT_METADATA_HISTORY rmh WHERE rmh.id = ? ORDER BY rmh.observed_at ASC;")?
+        .query_map([id], DbMetaDataSnapshot::map_row)?
+        .collect::<rusqlite::Result<_>>()?;
+    if let Some(latest) = snapshots.last() {
+        println!("Robot #{} (`{}` by {}), {} snapshots", id, latest.metadata.name, latest.metadata.added_by_display_name, snapshots.len());
+    } else {
+        println!("No history for robot #{}", id);
+        return Ok(());
+    }
+    println!("{:<20} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8}", "observed", "ranking", "rents", "buys", "featured", "combat", "cosmetic");
+    for snapshot in snapshots {
+        let observed = if snapshot.observed_at == 0 {
+            "(before history)".to_owned()
+        } else {
+            db.query_row("SELECT datetime(?, 'unixepoch');", [snapshot.observed_at], |row| row.get(0))?
+        };
+        let meta = snapshot.metadata;
+        println!("{:<20} {:>8} {:>8} {:>8} {:>8} {:>8.2} {:>8.2}", observed, meta.total_robot_ranking, meta.rent_count, meta.buy_count, meta.featured, meta.combat_rating, meta.cosmetic_rating);
+    }
+    Ok(())
+}
+
+pub fn export(db: &mut Connection, args: &ExportArgs) -> Result<()> {
+    let mut out: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(std::io::BufWriter::new(std::fs::File::create(path)?)),
+        None => Box::new(std::io::BufWriter::new(std::io::stdout().lock())),
+    };
+    let table = if args.live_only { "ROBOT_LIVE" } else { "ROBOT_METADATA" };
+    let (conditions, params) = args.dates.conditions();
+    let filter = if conditions.is_empty() {
+        String::new()
+    } else {
+        format!("WHERE {} ", conditions.join(" AND "))
+    };
+    let query = if args.cubes {
+        format!("SELECT rm.*, rc.cube_data, rc.colour_data, rc.cube_amounts FROM {} rm LEFT JOIN ROBOT_CUBES rc ON rm.id = rc.id {}ORDER BY rm.id ASC;", table, filter)
+    } else {
+        format!("SELECT rm.* FROM {} rm {}ORDER BY rm.id ASC;", table, filter)
+    };
+    let mut stmt = db.prepare(&query)?;
+    let mut rows = stmt.query(rusqlite::params_from_iter(params))?;
+    let mut count = 0;
+    while let Some(row) = rows.next()? {
+        let record = ExportRecord {
+            metadata: DbMetaData::map_row(row)?,
+            added_at: row.get("added_at")?,
+            expires_at: row.get("expires_at")?,
+            cube_data: if args.cubes { row.get("cube_data")? } else { None },
+            colour_data: if args.cubes { row.get("colour_data")? } else { None },
+            cube_amounts: if args.cubes { row.get("cube_amounts")? } else { None },
+        };
+        serde_json::to_writer(&mut out, &record).map_err(std::io::Error::from)?;
+        out.write_all(b"\n")?;
+        count += 1;
+    }
+    out.flush()?;
+    tracing::debug!("Exported {} robots", count);
+    Ok(())
+}
+
+/// Print the robots best matching a full-text query, best first
+pub fn search(db: &mut Connection, args: &SearchArgs) -> Result<()> {
+    let (conditions, dates) = args.dates.conditions();
+    let filter: String = conditions.iter().map(|condition| format!("AND {} ", condition)).collect();
+    let query = format!(
+        "SELECT rm.id, rm.name, rm.added_by_display_name, rm.cpu FROM ROBOT_SEARCH
+        INNER JOIN ROBOT_METADATA rm ON rm.id = ROBOT_SEARCH.rowid
+        WHERE ROBOT_SEARCH MATCH ? {}ORDER BY ROBOT_SEARCH.rank LIMIT ?;", filter);
+    let limit = args.limit as i64;
+    let mut params: Vec<&dyn rusqlite::ToSql> = vec![&args.query];
+    params.extend(dates.iter().map(|date| date as &dyn rusqlite::ToSql));
+    params.push(&limit);
+    let matches: Vec<(usize, String, String, usize)> = db.prepare(&query)?
+        .query_map(params.as_slice(), |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))?
+        .collect::<rusqlite::Result<_>>()
+        .map_err(|e| match e {
+            // the statement itself is fine once prepared, so these are mistakes in the query (which only show up once it's run)
+            rusqlite::Error::SqliteFailure(rusqlite::ffi::Error { code: rusqlite::ErrorCode::Unknown, .. }, Some(msg)) =>
+                Error::Config(format!("invalid search `{}`: {}", args.query, msg)),
+            e => e.into(),
+        })?;
+    if matches.is_empty() {
+        println!("No robots match `{}`", args.query);
+        return Ok(());
+    }
+    let folder = args.thumbnails.clone().unwrap_or_default();
+    println!("{:>8} {:<24} {:<20} {:>6} thumbnail", "id", "name", "author", "cpu");
+    for (id, name, author, cpu) in matches {
+        let thumbnail = folder.join(crate::thumbnails::filename(id, &name));
+        println!("{:>8} {:<24} {:<20} {:>6} {}", id, name, author, cpu, thumbnail.display());
+    }
+    Ok(())
+}
+
+pub fn print_stats(db: &mut Connection) -> Result<()> {
+    let count = |table: &str| -> rusqlite::Result<usize> {
+        db.query_row(&format!("SELECT COUNT(*) FROM {};", table), [], |row| row.get(0))
+    };
+    let version: usize = db.query_row("PRAGMA user_version;", [], |row| row.get(0))?;
+    println!("Schema version:         {}", version);
+    println!("Robots (metadata):      {}", count("ROBOT_METADATA")?);
+    println!("Robots (cubes):         {}", count("ROBOT_CUBES")?);
+    println!("Robots (live):          {}", count("ROBOT_LIVE")?);
+    println!("Robots (archived only): {}", count("ROBOT_ARCHIVED_ONLY")?);
+    println!("Missing robot IDs:      {}", count("ROBOT_MISSING")?);
+    println!("Metadata snapshots:     {}", count("ROBOT_METADATA_HISTORY")?);
+    println!("Raw API responses:      {}", count("ROBOT_RAW_RESPONSE")?);
+    println!("Queued thumbnails:      {}", count("THUMBNAIL_QUEUE")?);
+    let requests_today: usize = db.query_row(
+        "SELECT COALESCE(SUM(requests), 0) FROM API_USAGE WHERE day = CAST(strftime('%s', 'now') AS INTEGER) / 86400;", [], |row| row.get(0))?;
+    println!("API requests today:     {}", requests_today);
+    let (lowest, highest): (Option<usize>, Option<usize>) = db.query_row(
+        "SELECT MIN(id), MAX(id) FROM ROBOT_CUBES;", [], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    if let (Some(lowest), Some(highest)) = (lowest, highest) {
+        println!("Robot IDs:              #{} to #{}", lowest, highest);
+    }
+    let state: Option<(usize, usize)> = db.query_row(
+        "SELECT next_page, last_sequential_id FROM STATE WHERE id = 0;", [], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map(Some)
+        .or_else(|e| if let rusqlite::Error::QueryReturnedNoRows = e { Ok(None) } else { Err(e) })?;
+    if let Some((next_page, last_sequential_id)) = state {
+        println!("Sweep search page:      {}", next_page);
+        if last_sequential_id < u32::MAX as usize {
+            println!("Sweep robot ID:         #{}", last_sequential_id);
+        }
+    }
+    Ok(())
+}
+
+/// Show where each mode will resume, after resetting one if asked to
+pub fn state(db: &mut Connection, args: &StateArgs) -> Result<()> {
+    if let Some(mode) = args.reset {
+        db.execute("DELETE FROM STATE WHERE id = ?;", [mode.state_id()])?;
+        println!("Reset {} state, it will start from the beginning", mode.name());
+    }
+    println!("{:<8} {:>10} {:>10} {:>12}", "mode", "next page", "page size", "robot ID");
+    for mode in Mode::ALL {
+        let state = db
+            .prepare("SELECT * FROM STATE WHERE id = ?;")?
+            .query_map([mode.state_id()], DbState::map_row)?
+            .next()
+            .transpose()?;
+        match state {
+            Some(state) => {
+                let robot_id = if state.last_sequential_id < u32::MAX as usize {
+                    format!("#{}", state.last_sequential_id)
+                } else {
+                    "-".to_owned()
+                };
+                println!("{:<8} {:>10} {:>10} {:>12}", mode.name(), state.next_page, state.last_page_size, robot_id);
+            },
+            None => println!("{:<8} {:>10}", mode.name(), "not started"),
+        }
+    }
+    Ok(())
+}
+
+pub fn verify(db: &mut Connection) -> Result<()> {
+    let mut problems = Vec::new();
+    let integrity: Vec<String> = db.prepare("PRAGMA integrity_check;")?
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    if integrity != ["ok"] {
+        for msg in &integrity {
+            tracing::error!("Integrity check: {}", msg);
+        }
+        problems.push(format!("{} integrity problems", integrity.len()));
+    }
+    // robots which couldn't be downloaded are expected to have no cubes
+    let unavailable: usize = db.query_row(
+        "SELECT COUNT(*) FROM ROBOT_METADATA rm WHERE rm.id NOT IN (SELECT id FROM ROBOT_CUBES)
+            AND (rm.id IN (SELECT id FROM ROBOT_MISSING) OR rm.id IN (SELECT id FROM ROBOT_TOMBSTONE));",
+        [], |row| row.get(0))?;
+    if unavailable != 0 {
+        println!("{} robots have no cubes because they are missing or gone", unavailable);
+    }
+    let checks = [
+        ("robots with metadata but no cubes", "SELECT rm.id FROM ROBOT_METADATA rm WHERE rm.id NOT IN (SELECT id FROM ROBOT_CUBES)
+            AND rm.id NOT IN (SELECT id FROM ROBOT_MISSING) AND rm.id NOT IN (SELECT id FROM ROBOT_TOMBSTONE);"),
+        ("robots with cubes but no metadata", "SELECT rc.id FROM ROBOT_CUBES rc WHERE rc.id NOT IN (SELECT id FROM ROBOT_METADATA);"),
+        ("robots missing from the search index", "SELECT rm.id FROM ROBOT_METADATA rm WHERE rm.id NOT IN (SELECT rowid FROM ROBOT_SEARCH);"),
+    ];
+    for (what, query) in checks {
+        let ids: Vec<usize> = db.prepare(query)?
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        if !ids.is_empty() {
+            for id in &ids {
+                tracing::debug!(robot_id = id, "Robot #{}: {}", id, what);
+            }
+            problems.push(format!("{} {}", ids.len(), what));
+        }
+    }
+    if problems.is_empty() {
+        println!("Database is OK");
+        Ok(())
+    } else {
+        Err(Error::Inconsistent(problems.join(", ")))
+    }
+}
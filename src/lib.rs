@@ -0,0 +1,715 @@
+// libfj returns the (large) ureq::Error directly, so results carrying it are everywhere
+#![allow(clippy::result_large_err)]
+
+pub mod auth;
+pub mod client;
+pub mod config;
+pub mod entities;
+pub mod error;
+mod fetcher;
+mod inspect;
+mod logging;
+mod metrics;
+mod migrations;
+mod progress;
+mod raw;
+mod ratelimit;
+mod retry;
+mod shutdown;
+mod thumbnails;
+
+use std::sync::Arc;
+
+use auth::{Credentials, Session};
+use client::{Connect, FactoryClient, Fetched, HttpFactory, HttpSettings, Search};
+use config::{CliArgs, Command, FetchIdsArgs, Mode, NewArgs, SweepArgs};
+use error::{Error, Result};
+use progress::Progress;
+use retry::RetryPolicy;
+use entities::{Entity, DbMetaData, DbMetaDataSnapshot, DbCubeData, DbState, DbMissing, DbTombstone};
+
+use rusqlite::Connection;
+use tracing::{debug, error, info, warn};
+
+use libfj::robocraft::{FactoryRobotGetInfo, FactoryRobotListInfo};
+
+const DEFAULT_PAGE_SIZE: isize = 100;
+const PERIOD: usize = 100;
+const DEFAULT_WORKERS: usize = 1;
+
+/// Run the command given on the command line, against the live factory API
+pub fn run() -> Result<()> {
+    let config = config::parse()?;
+    if config.print_config {
+        print!("{}", config.effective_toml()?);
+        return Ok(());
+    }
+    logging::init(&config)?;
+    shutdown::install_handler()?;
+    let http = HttpSettings::from_config(&config);
+    let agent = http.agent()?;
+    let credentials = if config.uses_api() { Credentials::from_config(&config)? } else { Credentials::Anonymous };
+    let session = Arc::new(Session::new(agent.clone(), &http.account_url, credentials));
+    session.login()?;
+    run_with(&config, &move || Box::new(HttpFactory::new(agent.clone(), &http.api_url, session.clone())))
+}
+
+/// Run a command, talking to the factory API through clients from `connect`
+pub fn run_with(config: &CliArgs, connect: &Connect) -> Result<()> {
+    debug!("Opening & building database, roboshield be damned");
+    let db_path = config.database.clone().unwrap_or("rc_archive.db".to_owned());
+    let mut db = rusqlite::Connection::open(&db_path)?;
+    // build database structure
+    migrations::migrate(&mut db, config.migrate_only)?;
+    if config.migrate_only {
+        return Ok(());
+    }
+    let command = config.command.as_ref()
+        .ok_or_else(|| Error::Config("a command is required, unless using --migrate-only".to_owned()))?;
+
+    // commands which only use the database
+    match command {
+        Command::History(args) => return inspect::print_history(&mut db, args.id),
+        Command::Export(args) => return inspect::export(&mut db, args),
+        Command::Search(args) => return inspect::search(&mut db, args),
+        Command::Stats => return inspect::print_stats(&mut db),
+        Command::Verify => return inspect::verify(&mut db),
+        Command::State(args) => return inspect::state(&mut db, args),
+        Command::Rederive => return raw::rederive(&mut db),
+        _ => {},
+    }
+
+    metrics::start(config, &db_path)?;
+
+    // start thumbnail download threadpool (if folder provided)
+    let http = HttpSettings::from_config(config);
+    let thumbnail_retriever = match command {
+        Command::Thumbs(args) => Some(thumbnails::ThumbnailRetriever::new(
+            &args.folder,
+            ratelimit::RateLimiter::new(args.thumbnail_rate, None),
+            &http,
+            config.verbose,
+        )?),
+        _ => config.fetch().thumbnails.as_ref().map(|folder| thumbnails::ThumbnailRetriever::new(
+            folder,
+            ratelimit::RateLimiter::new(config.fetch().thumbnail_rate, None),
+            &http,
+            config.verbose,
+        )).transpose()?,
+    };
+    if let Some(tr) = thumbnail_retriever.as_ref() {
+        if let Command::Thumbs(_) = command {
+            debug!("Redownloading all thumbnails, watch out for ghosting");
+            tr.retrieve_all_known(&mut db)?;
+        } else {
+            tr.retrieve_unfinished(&mut db)?;
+        }
+    }
+
+    // begin scraping
+    let api = connect();
+    let api = api.as_ref();
+    let retry = RetryPolicy::from_config(config, &db_path)?;
+    let result = (|| -> Result<()> {
+        match command {
+            Command::New(NewArgs { watch: Some(interval), .. }) => {
+                watch_new_bots(&mut db, config, connect, api, &retry, &thumbnail_retriever, *interval)?;
+            },
+            Command::Sweep(SweepArgs { from_id, to_id, .. }) if from_id.is_some() || to_id.is_some() => {
+                download_bot_range(&mut db, config, connect, &retry, &thumbnail_retriever, *from_id, *to_id)?;
+            },
+            Command::Fetch(args) => {
+                download_bot_list(&mut db, config, connect, &retry, &thumbnail_retriever, args)?;
+            },
+            Command::Author(args) => {
+                download_author_bots(&mut db, config, connect, api, &retry, &thumbnail_retriever, &args.name)?;
+            },
+            Command::Revalidate(_) => {
+                revalidate_bots(&mut db, config, connect, &retry)?;
+            },
+            Command::Sweep(_) | Command::Scrape(_) | Command::New(_) => {
+                let mode = config.mode().unwrap_or(Mode::Sweep);
+                let mut state = build_state(&mut db, config, mode)?;
+                save_state(&mut db, &state)?;
+                if state.next_page == 0 {
+                    debug!("Beginning archival process, looking out for T-sticks");
+                } else {
+                    debug!("Resuming archival process at page {}, blaming Josh", state.next_page);
+                }
+                search_bots(&mut db, config, &mut state, api, &retry)?;
+                if shutdown::requested() {
+                    debug!("Skipping robot cubes download, shutting down");
+                } else if let Command::Scrape(_) = command {
+                    debug!("Downloading robot cubes data for all known robots");
+                    download_missing_bots(&mut db, config, api, &retry, &thumbnail_retriever)?;
+                } else {
+                    debug!("Looking for non-searchable bots, activating windowmaker module");
+                    download_all_bots(&mut db, &mut state, config, connect, &retry, &thumbnail_retriever)?;
+                }
+            },
+            _ => {},
+        }
+        Ok(())
+    })();
+
+    // even after an error, so that unfinished thumbnails are saved for the next run rather than lost
+    if let Some(tr) = thumbnail_retriever {
+        tr.finalize(&mut db)?;
+    }
+    result?;
+    metrics::finish(config, &db_path)?;
+    debug!("Done.");
+    Ok(())
+}
+
+fn watch_new_bots(db: &mut Connection, config: &CliArgs, connect: &Connect, api: &dyn FactoryClient, retry: &RetryPolicy, thumbnail_ret: &Option<thumbnails::ThumbnailRetriever>, interval: std::time::Duration) -> Result<()> {
+    let mut cycle = 1;
+    while !shutdown::requested() {
+        let start = std::time::Instant::now();
+        let (listed_before, downloaded_before) = count_bots(db)?;
+        let mut state = build_state(db, config, Mode::New)?;
+        let result = search_bots(db, config, &mut state, api, retry)
+            .and_then(|()| if shutdown::requested() {
+                Ok(())
+            } else {
+                download_all_bots(db, &mut state, config, connect, retry, thumbnail_ret)
+            });
+        match result {
+            Err(e @ Error::Database(_)) => return Err(e),
+            // the factory may well be back by the next cycle
+            Err(e) => error!("Watch cycle {} failed, trying again in {}s: {}", cycle, interval.as_secs(), e),
+            Ok(()) => {
+                let (listed_after, downloaded_after) = count_bots(db)?;
+                info!("Watch cycle {}: {} new robots listed, {} new robots downloaded in {:.1}s",
+                    cycle, listed_after - listed_before, downloaded_after - downloaded_before, start.elapsed().as_secs_f32());
+            },
+        }
+        cycle += 1;
+        if !shutdown::requested() {
+            debug!("Sleeping for {}s until next search, dreaming of electric sheep", interval.as_secs());
+        }
+        let wake_up = std::time::Instant::now() + interval;
+        while !shutdown::requested() && std::time::Instant::now() < wake_up {
+            std::thread::sleep(std::time::Duration::from_millis(250));
+        }
+    }
+    Ok(())
+}
+
+fn count_bots(db: &mut Connection) -> Result<(usize, usize)> {
+    let listed = db.query_row("SELECT COUNT(*) FROM ROBOT_METADATA;", [], |row| row.get(0))?;
+    let downloaded = db.query_row("SELECT COUNT(*) FROM ROBOT_CUBES;", [], |row| row.get(0))?;
+    Ok((listed, downloaded))
+}
+
+/// Load the saved state for `mode`, or start afresh when there isn't any (or the page size changed)
+fn build_state(db: &mut Connection, config: &CliArgs, mode: Mode) -> Result<DbState> {
+    let page_size = config.fetch().size;
+    let saved = db
+        .prepare("SELECT * FROM STATE WHERE id = ?;")?
+        .query_map([mode.state_id()], DbState::map_row)?
+        .next()
+        .transpose()?;
+    Ok(match saved {
+        // new always looks at the most recent robots, so there's nothing to resume (older versions saved a page anyway)
+        Some(state) if mode == Mode::New => DbState::fresh(state.id, page_size.unwrap_or(state.last_page_size)),
+        Some(state) if page_size.is_none_or(|size| size == state.last_page_size) => state,
+        _ => DbState::fresh(mode.state_id(), page_size.unwrap_or(DEFAULT_PAGE_SIZE)),
+    })
+}
+
+fn save_state(db: &mut Connection, state: &DbState) -> Result<()> {
+    db.execute(
+        "INSERT OR REPLACE INTO STATE (
+            id, next_page, last_page_size, last_sequential_id
+        ) VALUES (?, ?, ?, ?);",
+        state.to_params().as_slice()
+    )?;
+    Ok(())
+}
+
+fn search_bots(db: &mut Connection, config: &CliArgs, state: &mut DbState, api: &dyn FactoryClient, retry: &RetryPolicy) -> Result<()> {
+    let mut search = Search::all(state.next_page, state.last_page_size).buyable_only(!config.logged_in());
+    let progress = Progress::new("Searching (beep boop)", "pages", 0, config.verbose).without_misses();
+    loop {
+        if shutdown::requested() {
+            break;
+        }
+        let span = tracing::info_span!("search", op = "search", page = state.next_page);
+        let _span = span.enter();
+        let response = match retry.run(&format!("page {}", state.next_page), || api.list(&search)) {
+            Ok(response) => response,
+            Err(Error::BudgetExhausted) => {
+                warn!("Daily request budget used up, stopping search at page {}", state.next_page);
+                break;
+            },
+            Err(e) => {
+                error!("Failed to retrieve page {}, self-destructing...", state.next_page);
+                return Err(e);
+            }
+        };
+        if response.info.status_code != 200 {
+            error!("Got response status {}, self-destructing...", response.info.status_code);
+            return Err(Error::Status(response.info.status_code));
+        }
+        if response.info.response.roboshop_items.is_empty() {
+            progress.finish();
+            debug!("Got response page {} with no items, search has been defeated!", state.next_page);
+            // finished, so the next search starts over (only interrupted searches resume)
+            state.next_page = 0;
+            save_state(db, state)?;
+            break;
+        }
+        progress.tick(response.info.response.roboshop_items.len());
+        persist_metadata(db, response.info.response.roboshop_items, &response.raw)?;
+        if config.new_only() {
+            // without moving on, as the next run needs to look at the most recent robots again
+            progress.finish();
+            debug!("Stopping search before older robots are found");
+            break;
+        }
+        // prepare for next loop iteration
+        state.next_page += 1;
+        save_state(db, state)?;
+        search.page = state.next_page;
+    }
+    Ok(())
+}
+
+/// Save the metadata of robots from a list response, along with their raw JSON from `raw` (the whole response)
+fn persist_metadata(db: &mut Connection, robots: Vec<FactoryRobotListInfo>, raw: &serde_json::Value) -> Result<()> {
+    let transaction = db.transaction()?;
+    let observed_at = entities::unix_now();
+    let raw_items = raw::list_items(raw);
+    for robot in robots {
+        if let Some(item) = raw_items.get(&robot.item_id) {
+            raw::record(&transaction, robot.item_id, raw::LIST, item, observed_at)?;
+        }
+        upsert_metadata(&transaction, robot.into(), observed_at)?;
+    }
+    transaction.commit()?;
+    Ok(())
+}
+
+/// Save the latest metadata for a robot, keeping a snapshot in ROBOT_METADATA_HISTORY when it has changed
+fn upsert_metadata(db: &Connection, robot_meta: DbMetaData, observed_at: u64) -> Result<()> {
+    let previous = db
+        .prepare_cached("SELECT * FROM ROBOT_METADATA rm WHERE rm.id = ?;")?
+        .query_map([robot_meta.id], DbMetaData::map_row)?
+        .next()
+        .transpose()?;
+    if previous.as_ref() == Some(&robot_meta) {
+        return Ok(());
+    }
+    db.prepare_cached(
+        "INSERT OR REPLACE INTO ROBOT_METADATA (
+        id, name, description, thumbnail, added_by, added_by_display_name, added_date, expiry_date, cpu, total_robot_ranking, rent_count, buy_count, buyable, featured, combat_rating, cosmetic_rating, added_at, expires_at
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, CAST(strftime('%s', ?7) AS INTEGER), CAST(strftime('%s', ?8) AS INTEGER));"
+    )?.execute(robot_meta.to_params().as_slice())?;
+    let reindex = previous.as_ref().is_none_or(|previous| previous.name != robot_meta.name
+        || previous.description != robot_meta.description
+        || previous.added_by_display_name != robot_meta.added_by_display_name);
+    if reindex {
+        db.prepare_cached("DELETE FROM ROBOT_SEARCH WHERE rowid = ?;")?.execute([robot_meta.id])?;
+        db.prepare_cached(
+            "INSERT INTO ROBOT_SEARCH (rowid, name, description, added_by_display_name) VALUES (?, ?, ?, ?);"
+        )?.execute(rusqlite::params![robot_meta.id, robot_meta.name, robot_meta.description, robot_meta.added_by_display_name])?;
+    }
+    let snapshot = DbMetaDataSnapshot {
+        metadata: robot_meta,
+        observed_at,
+    };
+    db.prepare_cached(
+        "INSERT OR REPLACE INTO ROBOT_METADATA_HISTORY (
+        id, name, description, thumbnail, added_by, added_by_display_name, added_date, expiry_date, cpu, total_robot_ranking, rent_count, buy_count, buyable, featured, combat_rating, cosmetic_rating, observed_at
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);"
+    )?.execute(snapshot.to_params().as_slice())?;
+    Ok(())
+}
+
+fn download_author_bots(db: &mut Connection, config: &CliArgs, connect: &Connect, api: &dyn FactoryClient, retry: &RetryPolicy, thumbnail_ret: &Option<thumbnails::ThumbnailRetriever>, author: &str) -> Result<()> {
+    let page_size = config.fetch().size.unwrap_or(DEFAULT_PAGE_SIZE);
+    let mut page = 0;
+    let mut ids = Vec::new();
+    let mut search = Search::by_player(author, page, page_size).buyable_only(!config.logged_in());
+    while !shutdown::requested() {
+        let span = tracing::info_span!("search", op = "author", page, author);
+        let _span = span.enter();
+        debug!("Retrieving page {} of robots by {}", page, author);
+        let response = match retry.run(&format!("page {} of robots by {}", page, author), || api.list(&search)) {
+            Ok(response) => response,
+            Err(Error::BudgetExhausted) => {
+                warn!("Daily request budget used up, stopping search");
+                break;
+            },
+            Err(e) => return Err(e),
+        };
+        if response.info.status_code != 200 {
+            error!("Got response status {}, self-destructing...", response.info.status_code);
+            return Err(Error::Status(response.info.status_code));
+        }
+        if response.info.response.roboshop_items.is_empty() {
+            debug!("Got response page with no items");
+            break;
+        }
+        // the player search also matches similar names
+        let robots: Vec<FactoryRobotListInfo> = response.info.response.roboshop_items.into_iter()
+            .filter(|robot| robot.added_by.eq_ignore_ascii_case(author) || robot.added_by_display_name.eq_ignore_ascii_case(author))
+            .collect();
+        debug!("Got {} robots by {}", robots.len(), author);
+        ids.extend(robots.iter().map(|robot| robot.item_id));
+        persist_metadata(db, robots, &response.raw)?;
+        page += 1;
+        search.page = page;
+    }
+    if shutdown::requested() {
+        return Ok(());
+    }
+    info!("Found {} robots by {}, downloading their cubes", ids.len(), author);
+    let workers = config.fetch().workers.unwrap_or(DEFAULT_WORKERS);
+    fetcher::fetch_robots(ids.into_iter(), workers, connect, retry, "author", |id, result| {
+        match archive_result(db, config, id, result, thumbnail_ret)? {
+            None => Ok(false),
+            Some(_) => Ok(!shutdown::requested()),
+        }
+    })
+}
+
+/// Check whether stored robots are still available, recording when they stop being available (404 or 410)
+fn revalidate_bots(db: &mut Connection, config: &CliArgs, connect: &Connect, retry: &RetryPolicy) -> Result<()> {
+    let ids: Vec<usize> = db
+        .prepare("SELECT id FROM ROBOT_METADATA rm ORDER BY rm.id DESC;")?
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    debug!("Revalidating {} robots, checking for a pulse", ids.len());
+    let (mut live, mut gone, mut newly_gone, mut failed) = (0, 0, 0, 0);
+    let workers = config.fetch().workers.unwrap_or(DEFAULT_WORKERS);
+    fetcher::fetch_robots(ids.into_iter(), workers, connect, retry, "revalidate", |id, result| {
+        let status_code = match result {
+            Err(Error::BudgetExhausted) => {
+                warn!("Daily request budget used up, stopping at bot #{}", id);
+                return Ok(false);
+            },
+            Err(Error::Api(e)) => {
+                warn!("Failed to retrieve robot #{} ({}), skipping it", id, e);
+                failed += 1;
+                return Ok(!shutdown::requested());
+            },
+            Err(Error::Status(status_code)) => status_code,
+            Err(e) => return Err(e),
+            Ok(response) if response.info.status_code != 200 => response.info.status_code,
+            Ok(response) => {
+                let observed_at = entities::unix_now();
+                raw::record(db, id, raw::GET, &response.raw["response"], observed_at)?;
+                upsert_metadata(db, response.info.response.into(), observed_at)?;
+                db.execute("DELETE FROM ROBOT_TOMBSTONE WHERE id = ?;", [id])?;
+                live += 1;
+                return Ok(!shutdown::requested());
+            },
+        };
+        if !is_gone(status_code) {
+            // the factory didn't say whether it still exists
+            warn!("Robot #{} responded with status {}, skipping it", id, status_code);
+            failed += 1;
+            return Ok(!shutdown::requested());
+        }
+        let known_gone: usize = db.query_row("SELECT COUNT(*) FROM ROBOT_TOMBSTONE WHERE id = ?;", [id], |row| row.get(0))?;
+        if known_gone == 0 {
+            db.execute(
+                "INSERT INTO ROBOT_TOMBSTONE (
+                id, status_code, first_unavailable, last_checked
+                ) VALUES (?, ?, ?, ?);",
+                DbTombstone::new(id, status_code).to_params().as_slice()
+            )?;
+            newly_gone += 1;
+            debug!("Robot #{} is no longer available (status {})", id, status_code);
+        } else {
+            db.execute(
+                "UPDATE ROBOT_TOMBSTONE SET status_code = ?, last_checked = ? WHERE id = ?;",
+                rusqlite::params![status_code, entities::unix_now(), id]
+            )?;
+        }
+        gone += 1;
+        Ok(!shutdown::requested())
+    })?;
+    info!("Revalidated {} robots: {} live, {} no longer available ({} newly), {} could not be checked", live + gone + failed, live, gone, newly_gone, failed);
+    Ok(())
+}
+
+fn download_missing_bots(db: &mut Connection, config: &CliArgs, api: &dyn FactoryClient, retry: &RetryPolicy, thumbnail_ret: &Option<thumbnails::ThumbnailRetriever>) -> Result<()> {
+    let missing_bots: Vec<rusqlite::Result<DbMetaData>> = db
+        .prepare(
+            "SELECT * FROM ROBOT_METADATA rm WHERE rm.id NOT IN (SELECT id from ROBOT_CUBES rc);"
+        )?
+        .query_map([], DbMetaData::map_row)?.collect();
+    debug!("Found {} robots which need their cubes downloaded", missing_bots.len());
+    let progress = Progress::new("Downloading known robots", "IDs", missing_bots.len(), config.verbose);
+    for bot in missing_bots.into_iter().flatten() {
+        if shutdown::requested() {
+            break;
+        }
+        let span = tracing::info_span!("robot", op = "scrape", robot_id = bot.id);
+        let _span = span.enter();
+        let result = retry.run(&format!("robot #{}", bot.id), || api.get(bot.id));
+        let found = match archive_result(db, config, bot.id, result, thumbnail_ret)? {
+            None => break,
+            Some(missing) => missing.is_none(),
+        };
+        progress.tick(found as usize);
+    }
+    progress.finish();
+    Ok(())
+}
+
+fn persist_bot(db: &mut Connection, config: &CliArgs, response: Fetched<FactoryRobotGetInfo>, thumbnail_ret: &Option<thumbnails::ThumbnailRetriever>) -> Result<()> {
+    if response.info.status_code != 200 {
+        return Err(Error::Status(response.info.status_code));
+    }
+    let robo_data = response.info.response;
+    let observed_at = entities::unix_now();
+    raw::record(db, robo_data.item_id, raw::GET, &response.raw["response"], observed_at)?;
+    if config.new_only() {
+        info!("Found new robot #{} (`{}` by {}, {} CPU)", robo_data.item_id, robo_data.item_name, robo_data.added_by_display_name, robo_data.cpu);
+    }
+    let robot_meta: DbMetaData = robo_data.clone().into();
+    if let Some(tr) = thumbnail_ret.as_ref() {
+        tr.retrieve(&robot_meta);
+    }
+    upsert_metadata(db, robot_meta, observed_at)?;
+    upsert_cubes(db, &robo_data.into())?;
+    metrics::robot_fetched();
+    Ok(())
+}
+
+fn upsert_cubes(db: &Connection, robot_cubes: &DbCubeData) -> Result<()> {
+    db.prepare_cached(
+        "INSERT OR REPLACE INTO ROBOT_CUBES (
+        id, cube_data, colour_data, cube_amounts
+        ) VALUES (?, ?, ?, ?);"
+    )?.execute(robot_cubes.to_params().as_slice())?;
+    Ok(())
+}
+
+/// Persist a robot, or remember that it could not be retrieved (and why).
+/// The status code (0 when there was no response) is returned when the robot could not be retrieved.
+fn persist_or_record(db: &mut Connection, config: &CliArgs, id: usize, result: Result<Fetched<FactoryRobotGetInfo>>, thumbnail_ret: &Option<thumbnails::ThumbnailRetriever>) -> Result<Option<usize>> {
+    let status_code = match result.and_then(|response| persist_bot(db, config, response, thumbnail_ret)) {
+        Ok(()) => {
+            db.execute("DELETE FROM ROBOT_MISSING WHERE id = ?;", [id])?;
+            return Ok(None);
+        },
+        Err(Error::Status(status_code)) => {
+            debug!("Robot #{} responded with status {}, marking it as missing", id, status_code);
+            status_code
+        },
+        Err(Error::Api(e)) => {
+            debug!("Robot #{} could not be retrieved ({}), marking it as missing", id, e);
+            0
+        },
+        Err(e) => return Err(e),
+    };
+    db.execute(
+        "INSERT INTO ROBOT_MISSING (
+        id, status_code, attempts, first_checked, last_checked
+        ) VALUES (?, ?, ?, ?, ?)
+        ON CONFLICT(id) DO UPDATE SET
+            status_code = excluded.status_code,
+            attempts = attempts + 1,
+            last_checked = excluded.last_checked;",
+        DbMissing::new(id, status_code).to_params().as_slice()
+    )?;
+    Ok(Some(status_code))
+}
+
+/// Handle the result of retrieving a robot with `persist_or_record`.
+/// Returns `None` when the daily request budget is used up, which should stop the download.
+fn archive_result(db: &mut Connection, config: &CliArgs, id: usize, result: Result<Fetched<FactoryRobotGetInfo>>, thumbnail_ret: &Option<thumbnails::ThumbnailRetriever>) -> Result<Option<Option<usize>>> {
+    match result {
+        Err(Error::BudgetExhausted) => {
+            warn!("Daily request budget used up, stopping at bot #{}", id);
+            Ok(None)
+        },
+        result => persist_or_record(db, config, id, result, thumbnail_ret).map(Some),
+    }
+}
+
+/// Does this status mean the robot doesn't exist (any more), rather than that retrieving it failed?
+fn is_gone(status_code: usize) -> bool {
+    matches!(status_code, 404 | 410)
+}
+
+/// Robots which aren't worth retrying, because they don't exist
+fn known_missing_bots(db: &mut Connection, config: &CliArgs) -> Result<std::collections::HashSet<usize>> {
+    if config.recheck_missing() {
+        return Ok(std::collections::HashSet::new());
+    }
+    let failures: Vec<(usize, usize)> = db
+        .prepare("SELECT id, status_code FROM ROBOT_MISSING;")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(failures.into_iter()
+        .filter(|(_, status_code)| is_gone(*status_code))
+        .map(|(id, _)| id)
+        .collect())
+}
+
+fn download_bot_list(db: &mut Connection, config: &CliArgs, connect: &Connect, retry: &RetryPolicy, thumbnail_ret: &Option<thumbnails::ThumbnailRetriever>, args: &FetchIdsArgs) -> Result<()> {
+    let mut ids = args.ids.clone();
+    if let Some(path) = &args.id_file {
+        let contents = if path.as_os_str() == "-" {
+            std::io::read_to_string(std::io::stdin())?
+        } else {
+            std::fs::read_to_string(path)?
+        };
+        ids.extend(config::parse_id_list(&contents)?);
+    }
+    let mut seen = std::collections::HashSet::new();
+    ids.retain(|id| seen.insert(*id));
+    debug!("Downloading {} requested robots", ids.len());
+    let mut report = std::collections::BTreeMap::new();
+    let workers = config.fetch().workers.unwrap_or(DEFAULT_WORKERS);
+    fetcher::fetch_robots(ids.clone().into_iter(), workers, connect, retry, "fetch", |id, result| {
+        let outcome = match archive_result(db, config, id, result, thumbnail_ret)? {
+            None => return Ok(false),
+            Some(None) => "archived".to_owned(),
+            Some(Some(0)) => "failed (no response)".to_owned(),
+            Some(Some(status_code)) => format!("missing (status {})", status_code),
+        };
+        report.insert(id, outcome);
+        Ok(!shutdown::requested())
+    })?;
+    let archived = report.values().filter(|outcome| *outcome == "archived").count();
+    println!("Archived {}/{} requested robots", archived, ids.len());
+    for id in ids {
+        println!("  #{}: {}", id, report.get(&id).map(|outcome| outcome.as_str()).unwrap_or("not attempted"));
+    }
+    Ok(())
+}
+
+fn download_bot_range(db: &mut Connection, config: &CliArgs, connect: &Connect, retry: &RetryPolicy, thumbnail_ret: &Option<thumbnails::ThumbnailRetriever>, from_id: Option<usize>, to_id: Option<usize>) -> Result<()> {
+    let from_id = match from_id {
+        Some(id) => id,
+        None => db.query_row("SELECT MAX(id) FROM ROBOT_METADATA;", [], |row| row.get::<_, Option<usize>>(0))?
+            .ok_or_else(|| Error::Config("No robots in database, --from-id is required".to_owned()))?,
+    };
+    let to_id = to_id.unwrap_or(0);
+    let missing = known_missing_bots(db, config)?;
+    debug!("Brute-forcing robots #{} to #{} (skipping {} known missing robots)", from_id, to_id, missing.len());
+    let total = (from_id.min(to_id)..=from_id.max(to_id)).filter(|id| !missing.contains(id)).count();
+    let ids: Box<dyn Iterator<Item = usize> + Send> = if from_id > to_id {
+        Box::new((to_id..=from_id).rev())
+    } else {
+        Box::new(from_id..=to_id)
+    };
+    let ids = ids.filter(move |id| !missing.contains(id));
+    let workers = config.fetch().workers.unwrap_or(DEFAULT_WORKERS);
+    let progress = Progress::new("Brute-forcing", "IDs", total, config.verbose);
+    fetcher::fetch_robots(ids, workers, connect, retry, "sweep", |id, result| {
+        metrics::sweep_at(id);
+        let found = match archive_result(db, config, id, result, thumbnail_ret)? {
+            None => return Ok(false),
+            Some(missing) => missing.is_none(),
+        };
+        progress.tick(found as usize);
+        if shutdown::requested() {
+            info!("Stopped at bot #{} (continue with --from-id {} --to-id {})", id, id, to_id);
+            return Ok(false);
+        }
+        Ok(true)
+    })?;
+    progress.finish();
+    Ok(())
+}
+
+fn download_all_bots(db: &mut Connection, state: &mut DbState, config: &CliArgs, connect: &Connect, retry: &RetryPolicy, thumbnail_ret: &Option<thumbnails::ThumbnailRetriever>) -> Result<()> {
+    let latest_bot_row: Vec<rusqlite::Result<DbMetaData>> = db
+        .prepare("SELECT * from ROBOT_METADATA rm ORDER BY rm.id DESC LIMIT 1;")?
+        .query_map([], DbMetaData::map_row)?.collect();
+
+    let latest_cube_row: Vec<rusqlite::Result<DbCubeData>> = db
+        .prepare("SELECT * from ROBOT_CUBES rc ORDER BY rc.id DESC LIMIT 1;")?
+        .query_map([], DbCubeData::map_row)?.collect();
+
+    let oldest_cube_row: Vec<rusqlite::Result<DbCubeData>> = db
+        .prepare("SELECT * from ROBOT_CUBES rc ORDER BY rc.id ASC LIMIT 1;")?
+        .query_map([], DbCubeData::map_row)?.collect();
+
+    if let Some(Ok(highest_bot)) = latest_bot_row.first() {
+        let highest_id = highest_bot.id;
+        let highest_cube_id = if let Some(Ok(highest_cubes)) = latest_cube_row.first() {
+            highest_cubes.id
+        } else {
+            0
+        };
+        let lowest_cube_id = if let Some(Ok(lowest_cubes)) = oldest_cube_row.first() {
+            lowest_cubes.id
+        } else {
+            usize::MAX
+        };
+
+        if state.last_sequential_id >= u32::MAX as usize {
+            state.last_sequential_id = highest_id;
+        }
+
+        // NOTE: IDs are gone through sequentially instead of just retrieving the known ones
+        // because the default user cannot search for non-buyable robots, despite them existing
+        // (and removed or banned robots never show up in searches, logged in or not).
+        // This creates gaps in known (i.e. searchable) IDs, despite IDs being sequential.
+        let workers = config.fetch().workers.unwrap_or(DEFAULT_WORKERS);
+        let missing = known_missing_bots(db, config)?;
+        if !missing.is_empty() {
+            debug!("Skipping {} robots which are known to be missing", missing.len());
+        }
+        if config.new_only() {
+            let ids = (highest_cube_id+1..=highest_id)
+                .filter(move |id| !missing.contains(id));
+            let progress = Progress::new("Downloading new robots", "IDs", ids.clone().count(), config.verbose);
+            fetcher::fetch_robots(ids, workers, connect, retry, "new", |id, result| {
+                let found = match archive_result(db, config, id, result, thumbnail_ret)? {
+                    None => return Ok(false),
+                    Some(missing) => missing.is_none(),
+                };
+                progress.tick(found as usize);
+                Ok(!shutdown::requested())
+            })?;
+            progress.finish();
+        } else {
+            debug!("Most recent bot has id #{}, existing data for #{} (ignoring down to #{}) to #{}", highest_id, state.last_sequential_id, lowest_cube_id, highest_cube_id);
+            let last_sequential_id = state.last_sequential_id;
+            let ids = (0..=highest_id).rev()
+                .filter(move |id| !(*id <= highest_cube_id && *id >= last_sequential_id))
+                .filter(move |id| !missing.contains(id));
+            let progress = Progress::new("Sweeping", "IDs", ids.clone().count(), config.verbose);
+            // results are handled in ID order, so everything above `id` has been done
+            fetcher::fetch_robots(ids, workers, connect, retry, "sweep", |id, result| {
+                metrics::sweep_at(id);
+                let found = match archive_result(db, config, id, result, thumbnail_ret)? {
+                    None => {
+                        debug!("Last persistent id is #{}", state.last_sequential_id);
+                        return Ok(false);
+                    },
+                    Some(missing) => missing.is_none(),
+                };
+                // robots newer than the last sweep are above the checkpoint, which mustn't move up
+                if state.last_sequential_id.saturating_sub(id) >= PERIOD {
+                    state.last_sequential_id = id - (id % PERIOD) + PERIOD;
+                    save_state(db, state)?;
+                }
+                progress.tick(found as usize);
+                if shutdown::requested() {
+                    if id < state.last_sequential_id {
+                        state.last_sequential_id = id;
+                    }
+                    save_state(db, state)?;
+                    info!("Stopped at bot #{}, last persistent id #{}", id, state.last_sequential_id);
+                    return Ok(false);
+                }
+                Ok(true)
+            })?;
+            progress.finish();
+        }
+    } else {
+        error!("No robots in database, cannot brute-force IDs!");
+    }
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use std::io::IsTerminal;
+use std::sync::Mutex;
+
+use tracing_subscriber::{fmt, EnvFilter};
+use tracing_subscriber::prelude::*;
+
+use crate::config::CliArgs;
+use crate::error::{Error, Result};
+use crate::progress;
+
+/// Log filter when `--log` isn't given
+const DEFAULT_FILTER: &str = "warn,rcarc=info";
+/// Log filter when `--log` isn't given, with `--verbose`
+const VERBOSE_FILTER: &str = "warn,rcarc=debug";
+
+/// Send log records to stderr, and to a JSON lines file when one is configured.
+///
+/// Work on a robot happens in a `robot` span, which carries the robot ID and the operation (e.g. `sweep`),
+/// so those end up in every record logged while working on it.
+pub fn init(config: &CliArgs) -> Result<()> {
+    let filter = match &config.log {
+        Some(filter) => filter.as_str(),
+        None if config.verbose => VERBOSE_FILTER,
+        None => DEFAULT_FILTER,
+    };
+    let filter = EnvFilter::try_new(filter)
+        .map_err(|e| Error::Config(format!("invalid log filter `{}`: {}", filter, e)))?;
+    let terminal = fmt::layer()
+        .without_time()
+        .with_target(false)
+        .with_ansi(std::io::stderr().is_terminal())
+        .with_writer(stderr);
+    let file = match &config.log_file {
+        Some(path) => {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?;
+            Some(fmt::layer()
+                .json()
+                .with_current_span(true)
+                .with_span_list(false)
+                .with_writer(Mutex::new(file)))
+        },
+        None => None,
+    };
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(terminal)
+        .with(file)
+        .try_init()
+        .map_err(|e| Error::Config(format!("cannot set up logging: {}", e)))
+}
+
+/// Log records share the terminal with the progress line, so get it out of the way first
+fn stderr() -> std::io::Stderr {
+    progress::clear();
+    std::io::stderr()
+}
@@ -0,0 +1,181 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::config::CliArgs;
+use crate::error::Result;
+
+/// Time between updates of the textfile (`--metrics-file`)
+const TEXTFILE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Tables which have their row counts exported
+const COUNTED_TABLES: &[&str] = &["ROBOT_METADATA", "ROBOT_CUBES", "ROBOT_METADATA_HISTORY", "ROBOT_MISSING", "ROBOT_TOMBSTONE", "ROBOT_RAW_RESPONSE", "THUMBNAIL_QUEUE"];
+
+static API_REQUESTS: AtomicU64 = AtomicU64::new(0);
+static API_ERRORS: Mutex<BTreeMap<String, u64>> = Mutex::new(BTreeMap::new());
+static ROBOTS_FETCHED: AtomicU64 = AtomicU64::new(0);
+static THUMBNAILS_DOWNLOADED: AtomicU64 = AtomicU64::new(0);
+static THUMBNAILS_FAILED: AtomicU64 = AtomicU64::new(0);
+static THUMBNAIL_QUEUE: AtomicI64 = AtomicI64::new(0);
+static SWEEP_ID: AtomicU64 = AtomicU64::new(0);
+
+/// A request was sent to the factory API
+pub fn api_request() {
+    API_REQUESTS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// The factory API responded with an unsuccessful `status` (or "transport" when it couldn't be reached)
+pub fn api_error(status: impl ToString) {
+    *API_ERRORS.lock().unwrap().entry(status.to_string()).or_insert(0) += 1;
+}
+
+/// A robot was downloaded and saved
+pub fn robot_fetched() {
+    ROBOTS_FETCHED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// A thumbnail download was queued
+pub fn thumbnail_queued() {
+    THUMBNAIL_QUEUE.fetch_add(1, Ordering::Relaxed);
+}
+
+/// A queued thumbnail download was started (or put off until the next run)
+pub fn thumbnail_started() {
+    THUMBNAIL_QUEUE.fetch_sub(1, Ordering::Relaxed);
+}
+
+/// A thumbnail download finished, successfully or not
+pub fn thumbnail_finished(saved: bool) {
+    if saved {
+        THUMBNAILS_DOWNLOADED.fetch_add(1, Ordering::Relaxed);
+    } else {
+        THUMBNAILS_FAILED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// The sweep has reached robot `id`
+pub fn sweep_at(id: usize) {
+    SWEEP_ID.store(id as u64, Ordering::Relaxed);
+}
+
+/// Start exporting metrics, as configured by `--metrics-listen` and `--metrics-file`.
+///
+/// Row counts are read using a separate (read-only) connection to the database at `db_path`.
+pub fn start(config: &CliArgs, db_path: &str) -> Result<()> {
+    if let Some(addr) = &config.metrics_listen {
+        let listener = std::net::TcpListener::bind(addr)?;
+        tracing::info!("Serving metrics on http://{}/metrics", listener.local_addr()?);
+        let db_path = db_path.to_owned();
+        std::thread::Builder::new()
+            .name("metrics.server".to_owned())
+            .spawn(move || serve(listener, &db_path))?;
+    }
+    if let Some(path) = &config.metrics_file {
+        let path = path.to_owned();
+        let db_path = db_path.to_owned();
+        std::thread::Builder::new()
+            .name("metrics.textfile".to_owned())
+            .spawn(move || loop {
+                if let Err(e) = write_textfile(&path, &db_path) {
+                    tracing::warn!("Failed to write metrics to {}: {}", path.display(), e);
+                }
+                std::thread::sleep(TEXTFILE_INTERVAL);
+            })?;
+    }
+    Ok(())
+}
+
+/// Write the final values to the textfile, if there is one
+pub fn finish(config: &CliArgs, db_path: &str) -> Result<()> {
+    if let Some(path) = &config.metrics_file {
+        write_textfile(path, db_path)?;
+    }
+    Ok(())
+}
+
+fn serve(listener: std::net::TcpListener, db_path: &str) {
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                tracing::debug!("Failed to accept metrics connection: {}", e);
+                continue;
+            }
+        };
+        // every path gets the metrics, so the request itself doesn't matter
+        let mut reader = std::io::BufReader::new(&stream);
+        let mut line = String::new();
+        while reader.read_line(&mut line).map(|n| n > 0 && line != "\r\n").unwrap_or(false) {
+            line.clear();
+        }
+        let body = render(db_path);
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(), body);
+        if let Err(e) = stream.write_all(response.as_bytes()) {
+            tracing::debug!("Failed to send metrics: {}", e);
+        }
+    }
+}
+
+/// Replace the textfile in one go, so a collector never reads half of it
+fn write_textfile(path: &Path, db_path: &str) -> std::io::Result<()> {
+    let mut tmp = PathBuf::from(path);
+    tmp.as_mut_os_string().push(".tmp");
+    std::fs::write(&tmp, render(db_path))?;
+    std::fs::rename(&tmp, path)
+}
+
+/// All metrics, in Prometheus text format
+fn render(db_path: &str) -> String {
+    let mut out = String::new();
+    metric(&mut out, "rcarc_api_requests_total", "counter", "Requests sent to the factory API, including retries", &[
+        ("", API_REQUESTS.load(Ordering::Relaxed).to_string()),
+    ]);
+    let errors: Vec<(String, String)> = API_ERRORS.lock().unwrap().iter()
+        .map(|(status, count)| (format!("{{status=\"{}\"}}", status), count.to_string()))
+        .collect();
+    metric(&mut out, "rcarc_api_errors_total", "counter", "Unsuccessful factory API responses, by status code", &errors);
+    metric(&mut out, "rcarc_robots_fetched_total", "counter", "Robots downloaded and saved", &[
+        ("", ROBOTS_FETCHED.load(Ordering::Relaxed).to_string()),
+    ]);
+    metric(&mut out, "rcarc_thumbnails_total", "counter", "Thumbnail downloads, by outcome", &[
+        ("{outcome=\"downloaded\"}", THUMBNAILS_DOWNLOADED.load(Ordering::Relaxed).to_string()),
+        ("{outcome=\"failed\"}", THUMBNAILS_FAILED.load(Ordering::Relaxed).to_string()),
+    ]);
+    metric(&mut out, "rcarc_thumbnail_queue_depth", "gauge", "Thumbnail downloads waiting to be started", &[
+        ("", THUMBNAIL_QUEUE.load(Ordering::Relaxed).max(0).to_string()),
+    ]);
+    metric(&mut out, "rcarc_sweep_current_id", "gauge", "Robot ID most recently handled by a sweep", &[
+        ("", SWEEP_ID.load(Ordering::Relaxed).to_string()),
+    ]);
+    match count_rows(db_path) {
+        Ok(counts) => {
+            let counts: Vec<(String, String)> = counts.into_iter()
+                .map(|(table, count)| (format!("{{table=\"{}\"}}", table), count.to_string()))
+                .collect();
+            metric(&mut out, "rcarc_db_rows", "gauge", "Rows in each database table", &counts);
+        },
+        Err(e) => tracing::debug!("Failed to count database rows for metrics: {}", e),
+    }
+    out
+}
+
+fn metric<L: AsRef<str>>(out: &mut String, name: &str, kind: &str, help: &str, samples: &[(L, String)]) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    for (labels, value) in samples {
+        let _ = writeln!(out, "{}{} {}", name, labels.as_ref(), value);
+    }
+}
+
+fn count_rows(db_path: &str) -> rusqlite::Result<Vec<(&'static str, usize)>> {
+    let db = rusqlite::Connection::open_with_flags(db_path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    COUNTED_TABLES.iter()
+        .map(|table| Ok((*table, db.query_row(&format!("SELECT COUNT(*) FROM {};", table), [], |row| row.get(0))?)))
+        .collect()
+}
@@ -0,0 +1,177 @@
+use rusqlite::Connection;
+
+use crate::error::{Error, Result};
+
+/// A change to the database schema
+struct Migration {
+    name: &'static str,
+    sql: &'static str,
+}
+
+/// Every schema change, oldest first.
+///
+/// A database's `user_version` is the number of these which have been applied to it.
+/// Never change a migration once it's been released, add another one instead.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        // databases from before migrations were tracked have some of this already, so it must not fail when they do
+        name: "initial schema",
+        sql: "
+        CREATE TABLE IF NOT EXISTS ROBOT_METADATA (
+            id INTEGER NOT NULL PRIMARY KEY,
+            name TEXT NOT NULL,
+            description TEXT NOT NULL,
+            thumbnail TEXT NOT NULL,
+            added_by TEXT NOT NULL,
+            added_by_display_name TEXT NOT NULL,
+            added_date TEXT NOT NULL,
+            expiry_date TEXT NOT NULL,
+            cpu INTEGER NOT NULL,
+            total_robot_ranking INTEGER NOT NULL,
+            rent_count INTEGER NOT NULL,
+            buy_count INTEGER NOT NULL,
+            buyable INTEGER NOT NULL,
+            featured INTEGER NOT NULL,
+            combat_rating REAL NOT NULL,
+            cosmetic_rating REAL NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS ROBOT_CUBES (
+            id INTEGER NOT NULL PRIMARY KEY,
+            cube_data TEXT NOT NULL,
+            colour_data TEXT NOT NULL,
+            cube_amounts TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS STATE (
+            id INTEGER NOT NULL PRIMARY KEY,
+            next_page INTEGER NOT NULL,
+            last_page_size INTEGER NOT NULL,
+            last_sequential_id INTEGER NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS THUMBNAIL_QUEUE (
+            id INTEGER NOT NULL PRIMARY KEY
+        );
+        CREATE TABLE IF NOT EXISTS ROBOT_METADATA_HISTORY (
+            id INTEGER NOT NULL,
+            name TEXT NOT NULL,
+            description TEXT NOT NULL,
+            thumbnail TEXT NOT NULL,
+            added_by TEXT NOT NULL,
+            added_by_display_name TEXT NOT NULL,
+            added_date TEXT NOT NULL,
+            expiry_date TEXT NOT NULL,
+            cpu INTEGER NOT NULL,
+            total_robot_ranking INTEGER NOT NULL,
+            rent_count INTEGER NOT NULL,
+            buy_count INTEGER NOT NULL,
+            buyable INTEGER NOT NULL,
+            featured INTEGER NOT NULL,
+            combat_rating REAL NOT NULL,
+            cosmetic_rating REAL NOT NULL,
+            observed_at INTEGER NOT NULL,
+            PRIMARY KEY (id, observed_at)
+        );
+        -- metadata from before history was kept was observed at an unknown time (0)
+        INSERT INTO ROBOT_METADATA_HISTORY
+            SELECT *, 0 FROM ROBOT_METADATA
+            WHERE NOT EXISTS (SELECT 1 FROM ROBOT_METADATA_HISTORY);
+        CREATE TABLE IF NOT EXISTS ROBOT_MISSING (
+            id INTEGER NOT NULL PRIMARY KEY,
+            status_code INTEGER NOT NULL,
+            attempts INTEGER NOT NULL,
+            first_checked INTEGER NOT NULL,
+            last_checked INTEGER NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS ROBOT_TOMBSTONE (
+            id INTEGER NOT NULL PRIMARY KEY,
+            status_code INTEGER NOT NULL,
+            first_unavailable INTEGER NOT NULL,
+            last_checked INTEGER NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS ROBOT_RAW_RESPONSE (
+            id INTEGER NOT NULL,
+            fetched_at INTEGER NOT NULL,
+            source TEXT NOT NULL,
+            payload BLOB NOT NULL,
+            PRIMARY KEY (id, fetched_at, source)
+        );
+        CREATE VIEW IF NOT EXISTS ROBOT_LIVE AS
+            SELECT rm.* FROM ROBOT_METADATA rm WHERE rm.id NOT IN (SELECT id FROM ROBOT_TOMBSTONE);
+        CREATE VIEW IF NOT EXISTS ROBOT_ARCHIVED_ONLY AS
+            SELECT rm.*, rt.status_code, rt.first_unavailable, rt.last_checked
+            FROM ROBOT_METADATA rm INNER JOIN ROBOT_TOMBSTONE rt ON rm.id = rt.id;
+        ",
+    },
+    Migration {
+        // NULL when the factory's date can't be parsed
+        name: "parsed added and expiry dates",
+        sql: "
+        ALTER TABLE ROBOT_METADATA ADD COLUMN added_at INTEGER;
+        ALTER TABLE ROBOT_METADATA ADD COLUMN expires_at INTEGER;
+        UPDATE ROBOT_METADATA SET
+            added_at = CAST(strftime('%s', added_date) AS INTEGER),
+            expires_at = CAST(strftime('%s', expiry_date) AS INTEGER);
+        CREATE INDEX ROBOT_METADATA_ADDED_AT ON ROBOT_METADATA (added_at);
+        CREATE INDEX ROBOT_METADATA_EXPIRES_AT ON ROBOT_METADATA (expires_at);
+        ",
+    },
+    Migration {
+        // each robot's row is its ID
+        name: "full-text search index",
+        sql: "
+        CREATE VIRTUAL TABLE ROBOT_SEARCH USING fts5(name, description, added_by_display_name);
+        INSERT INTO ROBOT_SEARCH (rowid, name, description, added_by_display_name)
+            SELECT id, name, description, added_by_display_name FROM ROBOT_METADATA;
+        ",
+    },
+    Migration {
+        // so that --daily-budget holds across runs
+        name: "factory API requests per day",
+        sql: "
+        CREATE TABLE API_USAGE (
+            day INTEGER NOT NULL PRIMARY KEY,
+            requests INTEGER NOT NULL
+        );
+        ",
+    },
+];
+
+/// Latest schema version which this build knows about
+pub fn latest_version() -> usize {
+    MIGRATIONS.len()
+}
+
+/// Bring the database schema up to date, all in one transaction.
+///
+/// Databases with a newer schema than this build knows about are refused.
+/// With `dry_run`, the migrations are applied and then rolled back, reporting what would be done.
+pub fn migrate(db: &mut Connection, dry_run: bool) -> Result<()> {
+    let transaction = db.transaction()?;
+    let version: usize = transaction.query_row("PRAGMA user_version;", [], |row| row.get(0))?;
+    if version > latest_version() {
+        return Err(Error::NewerSchema { found: version, supported: latest_version() });
+    }
+    let pending = &MIGRATIONS[version..];
+    if dry_run {
+        println!("Database schema version {} (latest: {}), pending migrations: {}", version, latest_version(), pending.len());
+    } else if !pending.is_empty() {
+        tracing::info!("Migrating database schema from version {} to {}", version, latest_version());
+    }
+    for (i, migration) in pending.iter().enumerate() {
+        let to_version = version + i + 1;
+        if dry_run {
+            println!("  {}: {}", to_version, migration.name);
+        }
+        tracing::debug!("Applying migration {} ({})", to_version, migration.name);
+        transaction.execute_batch(migration.sql)?;
+        transaction.pragma_update(None, "user_version", to_version)?;
+    }
+    if dry_run {
+        transaction.rollback()?;
+        if !pending.is_empty() {
+            println!("All migrations succeeded, nothing was changed (run without --migrate-only to apply them)");
+        }
+    } else {
+        transaction.commit()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,152 @@
+use std::io::{IsTerminal, Write};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Time between redraws of the progress line on a terminal
+const TTY_INTERVAL: Duration = Duration::from_millis(200);
+/// Time between progress lines when stdout isn't a terminal (e.g. piped into a log file)
+const PLAIN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Is a progress line currently drawn (without a newline) on the terminal?
+static LINE_DRAWN: AtomicBool = AtomicBool::new(false);
+
+/// Progress of a long-running operation, like a sweep over robot IDs.
+///
+/// On a terminal the progress is redrawn in place, otherwise a plain line is printed every `PLAIN_INTERVAL`.
+/// Nothing is displayed unless it's enabled (i.e. in verbose mode).
+pub struct Progress {
+    label: String,
+    unit: &'static str,
+    total: AtomicUsize,
+    processed: AtomicUsize,
+    found: AtomicUsize,
+    show_misses: bool,
+    started: Instant,
+    last_shown: Mutex<Option<Instant>>,
+    enabled: bool,
+    tty: bool,
+}
+
+impl Progress {
+    /// Track progress over `unit`s (IDs, pages, etc.), with `total` of them to go if it's known in advance (0 otherwise).
+    pub fn new(label: impl Into<String>, unit: &'static str, total: usize, enabled: bool) -> Self {
+        Self {
+            label: label.into(),
+            unit,
+            total: AtomicUsize::new(total),
+            processed: AtomicUsize::new(0),
+            found: AtomicUsize::new(0),
+            show_misses: true,
+            started: Instant::now(),
+            last_shown: Mutex::new(None),
+            enabled,
+            tty: std::io::stdout().is_terminal(),
+        }
+    }
+
+    /// Don't display a miss rate, for when each unit can find any number of robots (e.g. search pages)
+    pub fn without_misses(mut self) -> Self {
+        self.show_misses = false;
+        self
+    }
+
+    /// More work turned up
+    pub fn add_total(&self, count: usize) {
+        self.total.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Record that one unit was processed, finding `found` robots, without displaying it
+    pub fn record(&self, found: usize) {
+        self.processed.fetch_add(1, Ordering::Relaxed);
+        self.found.fetch_add(found, Ordering::Relaxed);
+    }
+
+    /// Record that one unit was processed, finding `found` robots, and display progress if it's been long enough
+    pub fn tick(&self, found: usize) {
+        self.record(found);
+        self.update();
+    }
+
+    /// Display progress if it's been long enough since it was last displayed
+    pub fn update(&self) {
+        if !self.enabled {
+            return;
+        }
+        let interval = if self.tty { TTY_INTERVAL } else { PLAIN_INTERVAL };
+        let now = Instant::now();
+        {
+            let mut last_shown = self.last_shown.lock().unwrap();
+            match *last_shown {
+                Some(last) if now.duration_since(last) < interval => return,
+                None if !self.tty && now.duration_since(self.started) < interval => return,
+                _ => *last_shown = Some(now),
+            }
+        }
+        self.show();
+    }
+
+    /// Display the final progress
+    pub fn finish(&self) {
+        if !self.enabled || self.processed.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        clear();
+        println!("{}", self.line());
+    }
+
+    fn show(&self) {
+        let line = self.line();
+        let mut stdout = std::io::stdout().lock();
+        if self.tty {
+            let _ = write!(stdout, "\r\x1b[2K{}", line);
+            let _ = stdout.flush();
+            LINE_DRAWN.store(true, Ordering::Relaxed);
+        } else {
+            let _ = writeln!(stdout, "{}", line);
+        }
+    }
+
+    fn line(&self) -> String {
+        let total = self.total.load(Ordering::Relaxed);
+        let processed = self.processed.load(Ordering::Relaxed);
+        let found = self.found.load(Ordering::Relaxed);
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let rate = if elapsed > 0.0 { processed as f64 / elapsed } else { 0.0 };
+        let mut line = if total != 0 {
+            format!("{}: {}/{} {} ({:.1}%), {} found", self.label, processed, total, self.unit, processed as f64 * 100.0 / total as f64, found)
+        } else {
+            format!("{}: {} {}, {} found", self.label, processed, self.unit, found)
+        };
+        if self.show_misses && processed != 0 {
+            line += &format!(", {:.1}% missed", processed.saturating_sub(found) as f64 * 100.0 / processed as f64);
+        }
+        line += &format!(", {:.1} req/s", rate);
+        if total > processed && rate > 0.0 {
+            line += &format!(", ETA {}", format_duration((total - processed) as f64 / rate));
+        }
+        line
+    }
+}
+
+/// Remove the progress line from the terminal, so that other messages can be printed.
+///
+/// The progress line is redrawn the next time it's updated.
+pub fn clear() {
+    if LINE_DRAWN.swap(false, Ordering::Relaxed) {
+        let mut stdout = std::io::stdout().lock();
+        let _ = write!(stdout, "\r\x1b[2K");
+        let _ = stdout.flush();
+    }
+}
+
+fn format_duration(secs: f64) -> String {
+    let secs = secs as u64;
+    if secs >= 60 * 60 {
+        format!("{}h{:02}m", secs / (60 * 60), secs / 60 % 60)
+    } else if secs >= 60 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
@@ -0,0 +1,142 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use rusqlite::{Connection, OptionalExtension};
+
+use crate::error::Result;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+/// Requests are saved to the database in batches of this many (and whenever the limiter is dropped)
+const SAVE_EVERY: usize = 50;
+
+/// Token bucket shared between threads, with an optional limit on requests per (UTC) day.
+///
+/// Requests can be counted in the database (see `persistent`), so that the daily limit holds across runs.
+pub struct RateLimiter {
+    per_second: Option<f64>,
+    daily_budget: Option<usize>,
+    state: Mutex<LimiterState>,
+}
+
+struct LimiterState {
+    tokens: f64,
+    last_refill: Instant,
+    day: u64,
+    used_today: usize,
+    store: Option<Connection>,
+    /// Requests made today which haven't been saved to the database yet
+    unsaved: usize,
+}
+
+impl RateLimiter {
+    /// Allow `per_second` requests per second (default: unlimited) and `daily_budget` requests per day (default: unlimited).
+    pub fn new(per_second: Option<f64>, daily_budget: Option<usize>) -> Self {
+        let per_second = per_second.filter(|rate| *rate > 0.0);
+        Self {
+            per_second,
+            daily_budget,
+            state: Mutex::new(LimiterState {
+                tokens: per_second.map(burst_size).unwrap_or(0.0),
+                last_refill: Instant::now(),
+                day: today(),
+                used_today: 0,
+                store: None,
+                unsaved: 0,
+            }),
+        }
+    }
+
+    /// Like `new`, but requests are counted in `db`'s API_USAGE table, including those made by earlier runs today
+    pub fn persistent(per_second: Option<f64>, daily_budget: Option<usize>, db: Connection) -> Result<Self> {
+        let limiter = Self::new(per_second, daily_budget);
+        {
+            let mut state = limiter.state.lock().unwrap();
+            state.used_today = db
+                .query_row("SELECT requests FROM API_USAGE WHERE day = ?;", [state.day], |row| row.get(0))
+                .optional()?
+                .unwrap_or(0);
+            state.store = Some(db);
+        }
+        Ok(limiter)
+    }
+
+    /// Wait until a request is allowed.
+    ///
+    /// Returns false (without waiting) when the daily budget has been used up.
+    pub fn acquire(&self) -> bool {
+        loop {
+            let mut state = self.state.lock().unwrap();
+            let day = today();
+            if state.day != day {
+                state.save();
+                state.day = day;
+                state.used_today = 0;
+            }
+            if let Some(budget) = self.daily_budget {
+                if state.used_today >= budget {
+                    return false;
+                }
+            }
+            if let Some(rate) = self.per_second {
+                let now = Instant::now();
+                let refill = now.duration_since(state.last_refill).as_secs_f64() * rate;
+                state.tokens = (state.tokens + refill).min(burst_size(rate));
+                state.last_refill = now;
+                if state.tokens < 1.0 {
+                    let wait = Duration::from_secs_f64((1.0 - state.tokens) / rate);
+                    drop(state);
+                    std::thread::sleep(wait);
+                    continue;
+                }
+                state.tokens -= 1.0;
+            }
+            state.used_today += 1;
+            state.unsaved += 1;
+            if state.unsaved >= SAVE_EVERY {
+                state.save();
+            }
+            return true;
+        }
+    }
+}
+
+impl Drop for RateLimiter {
+    fn drop(&mut self) {
+        self.state.lock().unwrap().save();
+    }
+}
+
+impl LimiterState {
+    /// Add the unsaved requests to the database (if any), catching up with requests made by other runs meanwhile
+    fn save(&mut self) {
+        let db = match &self.store {
+            Some(db) if self.unsaved > 0 => db,
+            _ => return,
+        };
+        let saved = db
+            .prepare_cached(
+                "INSERT INTO API_USAGE (day, requests) VALUES (?, ?)
+                ON CONFLICT(day) DO UPDATE SET requests = requests + excluded.requests;"
+            )
+            .and_then(|mut stmt| stmt.execute(rusqlite::params![self.day, self.unsaved]))
+            .and_then(|_| db.query_row("SELECT requests FROM API_USAGE WHERE day = ?;", [self.day], |row| row.get(0)));
+        match saved {
+            Ok(used) => {
+                self.used_today = used;
+                self.unsaved = 0;
+            },
+            Err(e) => tracing::warn!("Failed to save API request count to the database: {}", e),
+        }
+    }
+}
+
+fn burst_size(rate: f64) -> f64 {
+    rate.max(1.0)
+}
+
+fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() / SECONDS_PER_DAY)
+        .unwrap_or(0)
+}
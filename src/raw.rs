@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+
+use libfj::robocraft::{FactoryRobotGetInfo, FactoryRobotListInfo};
+use rusqlite::Connection;
+use serde::Deserialize;
+
+use crate::entities::{Entity, DbCubeData, DbMetaData, DbRawResponse};
+use crate::error::Result;
+
+/// Source of a robot's JSON which was found by searching
+pub const LIST: &str = "list";
+/// Source of a robot's JSON which was retrieved individually
+pub const GET: &str = "get";
+
+/// Each robot's JSON in a list response, by ID
+pub fn list_items(raw: &serde_json::Value) -> HashMap<usize, &serde_json::Value> {
+    raw["response"]["roboShopItems"].as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|item| Some((item["itemId"].as_u64()? as usize, item)))
+        .collect()
+}
+
+/// Save a robot's JSON, as it was received at `fetched_at`
+pub fn record(db: &Connection, id: usize, source: &str, json: &serde_json::Value, fetched_at: u64) -> Result<()> {
+    let raw = DbRawResponse::new(id, fetched_at, source, json)?;
+    db.prepare_cached(
+        "INSERT OR REPLACE INTO ROBOT_RAW_RESPONSE (
+        id, fetched_at, source, payload
+        ) VALUES (?, ?, ?, ?);"
+    )?.execute(raw.to_params().as_slice())?;
+    Ok(())
+}
+
+/// Replay every stored response, oldest first, to rebuild ROBOT_METADATA (and its history) and ROBOT_CUBES
+pub fn rederive(db: &mut Connection) -> Result<()> {
+    let transaction = db.transaction()?;
+    let (mut replayed, mut skipped) = (0, 0);
+    let mut robots = std::collections::HashSet::new();
+    {
+        let mut responses = transaction.prepare("SELECT * FROM ROBOT_RAW_RESPONSE ORDER BY fetched_at ASC, id ASC;")?;
+        let mut rows = responses.query([])?;
+        while let Some(row) = rows.next()? {
+            let raw = DbRawResponse::map_row(row)?;
+            if replay(&transaction, &raw)? {
+                replayed += 1;
+                robots.insert(raw.id);
+            } else {
+                skipped += 1;
+            }
+        }
+    }
+    transaction.commit()?;
+    println!("Re-derived {} robots from {} stored responses ({} skipped)", robots.len(), replayed, skipped);
+    Ok(())
+}
+
+/// Returns false when the response can't be used (which is logged)
+fn replay(db: &Connection, raw: &DbRawResponse) -> Result<bool> {
+    let json = match raw.json() {
+        Ok(json) => json,
+        Err(e) => {
+            tracing::warn!("Skipping unreadable {} response for robot #{} fetched at {}: {}", raw.source, raw.id, raw.fetched_at, e);
+            return Ok(false);
+        },
+    };
+    let robot = match raw.source.as_str() {
+        LIST => FactoryRobotListInfo::deserialize(&json).map(|robot| (robot.into(), None)),
+        GET => FactoryRobotGetInfo::deserialize(&json).map(|robot| (DbMetaData::from(robot.clone()), Some(DbCubeData::from(robot)))),
+        source => {
+            tracing::warn!("Skipping response for robot #{} from unknown source `{}`", raw.id, source);
+            return Ok(false);
+        },
+    };
+    let (metadata, cubes) = match robot {
+        Ok(robot) => robot,
+        Err(e) => {
+            tracing::warn!("Skipping invalid {} response for robot #{} fetched at {}: {}", raw.source, raw.id, raw.fetched_at, e);
+            return Ok(false);
+        },
+    };
+    crate::upsert_metadata(db, metadata, raw.fetched_at)?;
+    if let Some(cubes) = cubes {
+        crate::upsert_cubes(db, &cubes)?;
+    }
+    Ok(true)
+}
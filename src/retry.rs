@@ -0,0 +1,100 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use rand::Rng;
+
+use crate::client::{ApiResult, Fetched};
+use crate::config::CliArgs;
+use crate::error::{Error, Result};
+use crate::metrics;
+use crate::ratelimit::RateLimiter;
+use crate::shutdown;
+
+const DEFAULT_ATTEMPTS: usize = 5;
+const DEFAULT_BASE_DELAY_MS: u64 = 500;
+const MAX_DELAY: Duration = Duration::from_secs(60);
+/// How often a retry delay checks for a shutdown request
+const SLEEP_STEP: Duration = Duration::from_millis(250);
+
+/// Exponential backoff (with jitter) and rate limiting for factory API requests.
+#[derive(Clone)]
+pub struct RetryPolicy {
+    pub attempts: usize,
+    pub base_delay: Duration,
+    pub limiter: Arc<RateLimiter>,
+}
+
+impl RetryPolicy {
+    /// Requests are counted in the database at `db_path`, for the daily budget
+    pub fn from_config(config: &CliArgs, db_path: &str) -> Result<Self> {
+        let fetch = config.fetch();
+        let usage = rusqlite::Connection::open(db_path)?;
+        Ok(Self {
+            attempts: fetch.attempts.unwrap_or(DEFAULT_ATTEMPTS).max(1),
+            base_delay: Duration::from_millis(fetch.retry_delay.unwrap_or(DEFAULT_BASE_DELAY_MS)),
+            limiter: Arc::new(RateLimiter::persistent(fetch.api_rate, fetch.daily_budget, usage)?),
+        })
+    }
+
+    /// Run `request` until it succeeds, fails in a way that retrying won't fix, or the attempts are used up.
+    ///
+    /// Transport errors and 429/5xx status codes (both HTTP and in the response body) are retried.
+    /// Retry-After is honoured up to the maximum backoff delay, and a shutdown request cuts the wait short.
+    /// The last result is returned as-is once the retry budget is spent.
+    /// Every attempt counts towards the rate limit and daily request budget.
+    pub fn run<T>(&self, what: &str, mut request: impl FnMut() -> ApiResult<T>) -> Result<Fetched<T>> {
+        let mut attempt = 1;
+        loop {
+            if !self.limiter.acquire() {
+                return Err(Error::BudgetExhausted);
+            }
+            metrics::api_request();
+            let result = request();
+            match &result {
+                Ok(fetched) if fetched.info.status_code != 200 => metrics::api_error(fetched.info.status_code),
+                Ok(_) => {},
+                Err(ureq::Error::Status(code, _)) => metrics::api_error(code),
+                Err(ureq::Error::Transport(_)) => metrics::api_error("transport"),
+            }
+            let (reason, retry_after) = match &result {
+                Ok(fetched) if should_retry(fetched.info.status_code as u16) => (format!("response status {}", fetched.info.status_code), None),
+                Ok(_) => return result.map_err(Error::from),
+                Err(ureq::Error::Status(code, response)) if should_retry(*code) => {
+                    let retry_after = response.header("Retry-After")
+                        .and_then(|secs| secs.parse::<u64>().ok())
+                        .map(|secs| Duration::from_secs(secs).min(MAX_DELAY));
+                    (format!("HTTP status {}", code), retry_after)
+                },
+                Err(ureq::Error::Status(_, _)) => return result.map_err(Error::from),
+                Err(ureq::Error::Transport(t)) => (format!("transport error: {}", t), None),
+            };
+            if attempt >= self.attempts || shutdown::requested() {
+                tracing::warn!("Giving up on {} after {} attempts, last failure was {}", what, attempt, reason);
+                return result.map_err(Error::from);
+            }
+            let delay = retry_after.unwrap_or_else(|| self.backoff(attempt));
+            tracing::debug!("Attempt {}/{} of {} failed ({}), retrying in {:.1}s", attempt, self.attempts, what, reason, delay.as_secs_f32());
+            let wake_up = std::time::Instant::now() + delay;
+            while !shutdown::requested() && std::time::Instant::now() < wake_up {
+                std::thread::sleep(SLEEP_STEP.min(wake_up.saturating_duration_since(std::time::Instant::now())));
+            }
+            if shutdown::requested() {
+                return result.map_err(Error::from);
+            }
+            attempt += 1;
+        }
+    }
+
+    /// Delay before the next attempt: half of the exponential delay is fixed, the other half is random.
+    fn backoff(&self, attempt: usize) -> Duration {
+        let exponential = self.base_delay
+            .saturating_mul(1u32 << (attempt - 1).min(16))
+            .min(MAX_DELAY);
+        let half = exponential / 2;
+        half + half.mul_f64(rand::thread_rng().gen::<f64>())
+    }
+}
+
+fn should_retry(status: u16) -> bool {
+    status == 429 || (500..600).contains(&status)
+}
@@ -0,0 +1,22 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::error::{Error, Result};
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Handle SIGINT/SIGTERM by asking everything to wrap up.
+/// A second signal exits immediately.
+pub fn install_handler() -> Result<()> {
+    ctrlc::set_handler(|| {
+        if REQUESTED.swap(true, Ordering::SeqCst) {
+            tracing::warn!("Received second interrupt, exiting immediately");
+            std::process::exit(130);
+        }
+        tracing::warn!("Received interrupt, saving progress before exiting (interrupt again to exit immediately)");
+    }).map_err(|e| Error::Io(std::io::Error::other(e)))
+}
+
+/// Has a shutdown been requested?
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
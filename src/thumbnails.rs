@@ -1,84 +1,157 @@
 use threadpool::ThreadPool;
 use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 
+use crate::client::HttpSettings;
 use crate::entities::Entity;
+use crate::error::Result;
+use crate::metrics;
+use crate::progress::Progress;
+use crate::ratelimit::RateLimiter;
+use crate::shutdown;
 
 const THUMBNAIL_RETRIEVAL_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+const FINALIZE_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
 
 pub struct ThumbnailRetriever {
     handle: ThreadPool,
     folder: PathBuf,
-    verbose: bool,
+    limiter: Arc<RateLimiter>,
+    agent: ureq::Agent,
+    http: HttpSettings,
+    unfinished: Arc<Mutex<Vec<usize>>>,
+    progress: Arc<Progress>,
 }
 
 impl ThumbnailRetriever {
-    pub fn new(folder: impl AsRef<std::path::Path>, verbose: bool) -> Self {
+    pub fn new(folder: impl AsRef<std::path::Path>, limiter: RateLimiter, http: &HttpSettings, verbose: bool) -> Result<Self> {
         let folder = folder.as_ref().to_path_buf();
         if !(folder.exists() && folder.is_dir()) {
-            std::fs::create_dir_all(&folder).expect("Invalid thumbnail folder");
+            std::fs::create_dir_all(&folder)?;
         }
-        Self {
+        Ok(Self {
             handle: threadpool::Builder::new()
                 //.num_threads(42) // default to number of CPUs on the current system
                 .thread_name("thumbnail.retriever.x".to_string())
                 .build(),
             folder,
-            verbose,
-        }
+            limiter: Arc::new(limiter),
+            agent: http.agent()?,
+            http: http.clone(),
+            unfinished: Arc::new(Mutex::new(Vec::new())),
+            progress: Arc::new(Progress::new("Downloading thumbnails", "thumbnails", 0, verbose)),
+        })
     }
 
     pub fn retrieve(&self, metadata: &crate::DbMetaData) {
-        let url = metadata.thumbnail.clone();
-        let filename = format!("{} - {}.jpg",
-                        metadata.id,
-                        metadata.name.chars().filter(|c| c.is_ascii_alphanumeric() || c == &' ').collect::<String>(),
-                    );
-        let save_path = self.folder.join(filename);
-        let verbose = self.verbose;
-        self.handle.execute(move || retrieve_thumbnail(url, save_path, verbose));
+        let url = self.http.thumbnail_url(&metadata.thumbnail);
+        let save_path = self.folder.join(filename(metadata.id, &metadata.name));
+        let id = metadata.id;
+        let limiter = self.limiter.clone();
+        let agent = self.agent.clone();
+        let unfinished = self.unfinished.clone();
+        let progress = self.progress.clone();
+        let span = tracing::info_span!("robot", op = "thumbnail", robot_id = id);
+        progress.add_total(1);
+        metrics::thumbnail_queued();
+        self.handle.execute(move || {
+            let _span = span.enter();
+            metrics::thumbnail_started();
+            if shutdown::requested() {
+                // leave it for next time
+                unfinished.lock().unwrap().push(id);
+            } else if limiter.acquire() {
+                let saved = retrieve_thumbnail(&agent, url, save_path);
+                metrics::thumbnail_finished(saved);
+                progress.record(saved as usize);
+            }
+        });
     }
 
-    pub fn retrieve_all_known(&self, db: &mut rusqlite::Connection) {
-        let mut ret_statement = db.prepare("SELECT * from ROBOT_METADATA rm ORDER BY rm.id DESC").unwrap();
-        let iter = ret_statement.query_map([], crate::DbMetaData::map_row).unwrap();
+    pub fn retrieve_all_known(&self, db: &mut rusqlite::Connection) -> Result<()> {
+        let mut ret_statement = db.prepare("SELECT * from ROBOT_METADATA rm ORDER BY rm.id DESC")?;
+        let iter = ret_statement.query_map([], crate::DbMetaData::map_row)?;
         for meta in iter {
-            self.retrieve(&meta.unwrap());
+            self.retrieve(&meta?);
         }
-        if self.verbose {
-            println!("Handling {} thumbnail downloads (in progress: {})", self.handle.queued_count(), self.handle.active_count());
+        drop(ret_statement);
+        db.execute("DELETE FROM THUMBNAIL_QUEUE;", [])?;
+        tracing::debug!("Handling {} thumbnail downloads (in progress: {})", self.handle.queued_count(), self.handle.active_count());
+        Ok(())
+    }
+
+    /// Queue thumbnails which were left unfinished by an interrupted run
+    pub fn retrieve_unfinished(&self, db: &mut rusqlite::Connection) -> Result<()> {
+        let mut ret_statement = db.prepare("SELECT * from ROBOT_METADATA rm WHERE rm.id IN (SELECT id FROM THUMBNAIL_QUEUE) ORDER BY rm.id DESC")?;
+        let iter = ret_statement.query_map([], crate::DbMetaData::map_row)?;
+        let mut count = 0;
+        for meta in iter {
+            self.retrieve(&meta?);
+            count += 1;
+        }
+        drop(ret_statement);
+        db.execute("DELETE FROM THUMBNAIL_QUEUE;", [])?;
+        if count != 0 {
+            tracing::debug!("Resuming {} unfinished thumbnail downloads", count);
         }
+        Ok(())
     }
 
-    pub fn finalize(self) {
-        if self.verbose {
-            println!("Waiting for remaining thumbnail downloads: {} (in progress: {})", self.handle.queued_count(), self.handle.active_count());
+    /// Wait for thumbnail downloads to complete.
+    /// When shutting down, queued downloads are saved for the next run instead.
+    pub fn finalize(self, db: &mut rusqlite::Connection) -> Result<()> {
+        tracing::debug!("Waiting for remaining thumbnail downloads: {} (in progress: {})", self.handle.queued_count(), self.handle.active_count());
+        while self.handle.queued_count() + self.handle.active_count() != 0 {
+            self.progress.update();
+            std::thread::sleep(FINALIZE_POLL_INTERVAL);
         }
         self.handle.join();
+        self.progress.finish();
+        let unfinished = self.unfinished.lock().unwrap();
+        if !unfinished.is_empty() {
+            let transaction = db.transaction()?;
+            {
+                let mut queue_insert = transaction.prepare("INSERT OR REPLACE INTO THUMBNAIL_QUEUE (id) VALUES (?);")?;
+                for id in unfinished.iter() {
+                    queue_insert.execute([id])?;
+                }
+            }
+            transaction.commit()?;
+            tracing::info!("Saved {} unfinished thumbnail downloads for next time", unfinished.len());
+        }
+        Ok(())
     }
 }
 
-fn retrieve_thumbnail(url: String, path: PathBuf, verbose: bool) {
-    let response = ureq::get(&url)
+/// Name of a robot's thumbnail in the thumbnails folder
+pub fn filename(id: usize, name: &str) -> String {
+    format!("{} - {}.jpg",
+        id,
+        name.chars().filter(|c| c.is_ascii_alphanumeric() || c == &' ').collect::<String>(),
+    )
+}
+
+/// Returns true when the thumbnail was saved
+fn retrieve_thumbnail(agent: &ureq::Agent, url: String, path: PathBuf) -> bool {
+    let response = agent.get(&url)
         .timeout(THUMBNAIL_RETRIEVAL_TIMEOUT)
         .call();
     match response {
-        Err(e) => if verbose {
-            eprintln!("failed to retrieve thumbnail {} (url: {}): {}", path.display(), url, e);
+        Err(e) => {
+            tracing::warn!("failed to retrieve thumbnail {} (url: {}): {}", path.display(), url, e);
+            false
         },
         Ok(resp) => {
             let mut body = Vec::new(); // should be a retrieved image (jpg)
             if let Err(e) = resp.into_reader().read_to_end(&mut body) {
-                if verbose {
-                    eprintln!("failed to download thumbnail {} (url: {}): {}", path.display(), url, e);
-                }
-                return;
+                tracing::warn!("failed to download thumbnail {} (url: {}): {}", path.display(), url, e);
+                return false;
             }
             if let Err(e) = std::fs::write(&path, body) {
-                if verbose {
-                    eprintln!("failed to save thumbnail {} (url: {}): {}", path.display(), url, e);
-                }
-                return;
+                tracing::warn!("failed to save thumbnail {} (url: {}): {}", path.display(), url, e);
+                return false;
             }
+            true
         }
     }
 }
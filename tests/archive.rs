@@ -0,0 +1,292 @@
+mod common;
+
+use common::{count, Call, FakeFactory};
+
+#[test]
+fn scrape_pages_through_search_results() {
+    let db = common::database("scrape_pages");
+    let fake = FakeFactory::with_robots(250);
+    common::run(&fake, &db, &["scrape", "--size", "100"]).unwrap();
+
+    let lists: Vec<Call> = fake.calls().into_iter().filter(|call| matches!(call, Call::List { .. })).collect();
+    assert_eq!(lists, (0..4).map(|page| Call::List { page, page_size: 100 }).collect::<Vec<_>>());
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM ROBOT_METADATA;"), 250);
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM ROBOT_CUBES;"), 250);
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM ROBOT_METADATA_HISTORY;"), 250);
+    // the search finished, so the next one starts over and refreshes every robot's metadata
+    assert_eq!(count(&db, "SELECT next_page FROM STATE WHERE id = 2;"), 0);
+    fake.clear_calls();
+    common::run(&fake, &db, &["scrape", "--size", "100"]).unwrap();
+    let lists: Vec<Call> = fake.calls().into_iter().filter(|call| matches!(call, Call::List { .. })).collect();
+    assert_eq!(lists, (0..4).map(|page| Call::List { page, page_size: 100 }).collect::<Vec<_>>());
+}
+
+#[test]
+fn sweep_fills_gaps_in_search_results() {
+    let db = common::database("sweep_gaps");
+    let fake = FakeFactory::with_robots(50);
+    fake.unlist(10);
+    fake.unlist(20);
+    fake.remove_robot(5);
+    fake.remove_robot(15);
+    common::run(&fake, &db, &["sweep", "--size", "100"]).unwrap();
+
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM ROBOT_CUBES;"), 48);
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM ROBOT_CUBES WHERE id IN (10, 20);"), 2);
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM ROBOT_METADATA WHERE id IN (10, 20);"), 2);
+    // 0 has never existed, 5 and 15 were deleted
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM ROBOT_MISSING WHERE status_code = 404;"), 3);
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM ROBOT_MISSING WHERE id IN (0, 5, 15);"), 3);
+
+    // known missing robots are skipped next time
+    fake.clear_calls();
+    common::run(&fake, &db, &["sweep", "--size", "100"]).unwrap();
+    let gets = fake.gets();
+    assert!(!gets.contains(&5) && !gets.contains(&15));
+}
+
+#[test]
+fn logged_in_search_includes_unbuyable_robots() {
+    let db = common::database("logged_in_search");
+    let token = db.with_extension("token");
+    std::fs::write(&token, "secret\n").unwrap();
+    let fake = FakeFactory::with_robots(20);
+    fake.unlist(10);
+    fake.unlist(20);
+
+    let anonymous = common::database("anonymous_search");
+    common::run(&fake, &anonymous, &["scrape", "--size", "100"]).unwrap();
+    assert_eq!(count(&anonymous, "SELECT COUNT(*) FROM ROBOT_METADATA;"), 18);
+    common::run(&fake, &db, &["scrape", "--size", "100", "--token-file", token.to_str().unwrap()]).unwrap();
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM ROBOT_METADATA;"), 20);
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM ROBOT_CUBES WHERE id IN (10, 20);"), 2);
+}
+
+#[test]
+fn slow_robot_does_not_let_workers_run_ahead() {
+    let db = common::database("slow_robot");
+    let fake = FakeFactory::with_robots(300);
+    common::run(&fake, &db, &["scrape", "--size", "100"]).unwrap();
+    fake.delay_get(250, std::time::Duration::from_millis(500));
+    common::run(&fake, &db, &["sweep", "--size", "100", "--recheck-missing", "--workers", "4"]).unwrap();
+
+    // at most 16 robots (4 per worker) past the slow one can be retrieved before it's done
+    let ahead = fake.gets_while_delaying();
+    assert!(ahead > 0 && ahead < 16, "{} robots retrieved while waiting", ahead);
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM ROBOT_CUBES;"), 300);
+}
+
+#[test]
+fn transient_errors_are_retried() {
+    let db = common::database("transient_errors");
+    let fake = FakeFactory::with_robots(20);
+    fake.fail_get(7, 500, 2);
+    fake.fail_get(8, 429, 1);
+    common::run(&fake, &db, &["scrape", "--size", "100"]).unwrap();
+
+    assert_eq!(fake.gets().iter().filter(|id| **id == 7).count(), 3);
+    assert_eq!(fake.gets().iter().filter(|id| **id == 8).count(), 2);
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM ROBOT_CUBES;"), 20);
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM ROBOT_MISSING;"), 0);
+}
+
+#[test]
+fn persistent_errors_are_recorded() {
+    let db = common::database("persistent_errors");
+    let fake = FakeFactory::with_robots(20);
+    fake.fail_get(7, 500, usize::MAX);
+    fake.fail_get(9, 403, usize::MAX);
+    common::run(&fake, &db, &["scrape", "--size", "100", "--attempts", "3"]).unwrap();
+
+    // client errors aren't retried
+    assert_eq!(fake.gets().iter().filter(|id| **id == 7).count(), 3);
+    assert_eq!(fake.gets().iter().filter(|id| **id == 9).count(), 1);
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM ROBOT_CUBES;"), 18);
+    assert_eq!(count(&db, "SELECT status_code FROM ROBOT_MISSING WHERE id = 7;"), 500);
+    assert_eq!(count(&db, "SELECT status_code FROM ROBOT_MISSING WHERE id = 9;"), 403);
+    // robots which couldn't be downloaded aren't an inconsistency
+    common::run_local(&db, &["verify"]).unwrap();
+
+    // only robots which don't exist are skipped by later sweeps
+    fake.fail_get(7, 500, 0);
+    fake.fail_get(9, 403, 0);
+    fake.clear_calls();
+    common::run(&fake, &db, &["sweep", "--size", "100"]).unwrap();
+    let gets = fake.gets();
+    assert!(gets.contains(&7) && gets.contains(&9) && gets.contains(&0));
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM ROBOT_CUBES;"), 20);
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM ROBOT_MISSING WHERE id IN (7, 9);"), 0);
+    fake.clear_calls();
+    common::run(&fake, &db, &["sweep", "--size", "100"]).unwrap();
+    assert!(!fake.gets().contains(&0));
+}
+
+#[test]
+fn revalidate_only_buries_robots_which_are_gone() {
+    let db = common::database("revalidate");
+    let fake = FakeFactory::with_robots(10);
+    common::run(&fake, &db, &["scrape", "--size", "100"]).unwrap();
+    fake.remove_robot(3);
+    fake.fail_get(5, 503, usize::MAX);
+    fake.fail_get(6, 403, usize::MAX);
+    common::run(&fake, &db, &["revalidate", "--attempts", "2"]).unwrap();
+
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM ROBOT_TOMBSTONE;"), 1);
+    assert_eq!(count(&db, "SELECT status_code FROM ROBOT_TOMBSTONE WHERE id = 3;"), 404);
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM ROBOT_LIVE;"), 9);
+}
+
+#[test]
+fn failed_search_is_an_error() {
+    let db = common::database("failed_search");
+    let fake = FakeFactory::with_robots(20);
+    fake.fail_list(503);
+    let err = common::run(&fake, &db, &["scrape", "--attempts", "2"]).unwrap_err();
+
+    assert_eq!(err.exit_code(), 5);
+    assert_eq!(fake.calls().len(), 2);
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM ROBOT_METADATA;"), 0);
+}
+
+#[test]
+fn sweep_resumes_after_budget_runs_out() {
+    let db = common::database("sweep_resume");
+    let fake = FakeFactory::with_robots(300);
+    // 4 searches and 150 robots, so the sweep stops halfway
+    common::run(&fake, &db, &["sweep", "--size", "100", "--daily-budget", "154"]).unwrap();
+    let first = fake.gets();
+    assert_eq!(first.len(), 150);
+    assert_eq!(first.first(), Some(&300));
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM ROBOT_CUBES;"), 150);
+    let resume_at = count(&db, "SELECT last_sequential_id FROM STATE WHERE id = 0;");
+    assert!(resume_at < 300);
+
+    fake.clear_calls();
+    common::run(&fake, &db, &["sweep", "--size", "100"]).unwrap();
+    // the finished search starts over, but robots above the resume point aren't fetched again
+    assert_eq!(fake.calls().first(), Some(&Call::List { page: 0, page_size: 100 }));
+    let second = fake.gets();
+    assert!(second.iter().all(|id| *id < resume_at));
+    assert!(second.contains(&0));
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM ROBOT_CUBES;"), 300);
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM ROBOT_MISSING;"), 1);
+}
+
+#[test]
+fn daily_budget_holds_across_runs() {
+    let db = common::database("budget_across_runs");
+    let fake = FakeFactory::with_robots(20);
+    // a search and 9 robots
+    common::run(&fake, &db, &["scrape", "--size", "100", "--daily-budget", "10"]).unwrap();
+    assert_eq!(fake.calls().len(), 10);
+    assert_eq!(count(&db, "SELECT SUM(requests) FROM API_USAGE;"), 10);
+
+    fake.clear_calls();
+    common::run(&fake, &db, &["scrape", "--size", "100", "--daily-budget", "10"]).unwrap();
+    assert!(fake.calls().is_empty());
+    common::run(&fake, &db, &["scrape", "--size", "100", "--daily-budget", "15"]).unwrap();
+    assert_eq!(fake.calls().len(), 5);
+    assert_eq!(count(&db, "SELECT SUM(requests) FROM API_USAGE;"), 15);
+}
+
+#[test]
+fn resumed_sweep_handles_robots_above_checkpoint() {
+    let db = common::database("sweep_above_checkpoint");
+    let fake = FakeFactory::with_robots(300);
+    common::run(&fake, &db, &["sweep", "--size", "100", "--daily-budget", "154"]).unwrap();
+    let checkpoint = count(&db, "SELECT last_sequential_id FROM STATE WHERE id = 0;");
+    assert!(checkpoint < 300);
+
+    for id in 301..=310 {
+        fake.add_robot(id);
+    }
+    fake.clear_calls();
+    common::run(&fake, &db, &["sweep", "--size", "100"]).unwrap();
+    assert!(fake.gets().starts_with(&[310, 309]));
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM ROBOT_CUBES;"), 310);
+    assert!(count(&db, "SELECT last_sequential_id FROM STATE WHERE id = 0;") <= checkpoint);
+}
+
+#[test]
+fn new_always_searches_the_first_page() {
+    let db = common::database("new_first_page");
+    let fake = FakeFactory::with_robots(150);
+    common::run(&fake, &db, &["new", "--size", "100"]).unwrap();
+    assert_eq!(count(&db, "SELECT next_page FROM STATE WHERE id = 1;"), 0);
+    for id in 151..=160 {
+        fake.add_robot(id);
+    }
+    // as left by a run which was interrupted while downloading
+    {
+        let conn = rusqlite::Connection::open(&db).unwrap();
+        conn.execute("UPDATE STATE SET next_page = 1 WHERE id = 1;", []).unwrap();
+    }
+    fake.clear_calls();
+    common::run(&fake, &db, &["new", "--size", "100"]).unwrap();
+    assert_eq!(fake.calls().first(), Some(&Call::List { page: 0, page_size: 100 }));
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM ROBOT_CUBES;"), 160);
+}
+
+#[test]
+fn raw_responses_can_be_rederived() {
+    let db = common::database("rederive");
+    let fake = FakeFactory::with_robots(30);
+    common::run(&fake, &db, &["scrape", "--size", "100"]).unwrap();
+    // one from the search and one from retrieving it
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM ROBOT_RAW_RESPONSE WHERE source = 'list';"), 30);
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM ROBOT_RAW_RESPONSE WHERE source = 'get';"), 30);
+
+    {
+        let conn = rusqlite::Connection::open(&db).unwrap();
+        conn.execute_batch("DELETE FROM ROBOT_METADATA; DELETE FROM ROBOT_CUBES; DELETE FROM ROBOT_METADATA_HISTORY;").unwrap();
+    }
+    common::run_local(&db, &["rederive"]).unwrap();
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM ROBOT_METADATA;"), 30);
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM ROBOT_CUBES;"), 30);
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM ROBOT_CUBES WHERE cube_data = 'cubes17';"), 1);
+}
+
+#[test]
+fn export_filters_by_date() {
+    let db = common::database("export_dates");
+    let fake = FakeFactory::with_robots(10);
+    for id in 1..=4 {
+        fake.set_dates(id, "2021-06-15T12:30:00.5", "2022-06-15T12:30:00.5");
+    }
+    common::run(&fake, &db, &["scrape", "--size", "100"]).unwrap();
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM ROBOT_METADATA WHERE added_at = 1623760200 AND expires_at = 1655296200;"), 4);
+
+    let exported = |filter: &[&str]| -> usize {
+        let out = db.with_extension("jsonl");
+        let mut cli = vec!["export", "--output", out.to_str().unwrap()];
+        cli.extend_from_slice(filter);
+        common::run_local(&db, &cli).unwrap();
+        std::fs::read_to_string(&out).unwrap().lines().count()
+    };
+    assert_eq!(exported(&[]), 10);
+    assert_eq!(exported(&["--added-before", "2022-01-01"]), 4);
+    assert_eq!(exported(&["--added-after", "2022-01-01T00:00:00Z"]), 6);
+    assert_eq!(exported(&["--added-after", "2021-06-15T12:30", "--expires-before", "1655296201"]), 4);
+    assert_eq!(exported(&["--expires-after", "2023-01-01", "--expires-before", "2023-01-02", "--cubes"]), 6);
+}
+
+#[test]
+fn search_index_follows_changes() {
+    let db = common::database("search_index");
+    let fake = FakeFactory::with_robots(10);
+    fake.rename(3, "Hover Tank");
+    fake.rename(4, "Tank Destroyer");
+    common::run(&fake, &db, &["scrape", "--size", "100"]).unwrap();
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM ROBOT_SEARCH WHERE ROBOT_SEARCH MATCH 'tank';"), 2);
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM ROBOT_SEARCH WHERE ROBOT_SEARCH MATCH 'added_by_display_name:\"Player 1\"';"), 4);
+
+    fake.rename(3, "Hover Jet");
+    common::run(&fake, &db, &["fetch", "3"]).unwrap();
+    assert_eq!(count(&db, "SELECT rowid FROM ROBOT_SEARCH WHERE ROBOT_SEARCH MATCH 'tank';"), 4);
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM ROBOT_SEARCH;"), 10);
+
+    common::run_local(&db, &["search", "hover OR tank", "--added-after", "2022-01-01"]).unwrap();
+    let err = common::run_local(&db, &["search", "\"unbalanced"]).unwrap_err();
+    assert_eq!(err.exit_code(), 2);
+    common::run_local(&db, &["verify"]).unwrap();
+}
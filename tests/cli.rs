@@ -0,0 +1,29 @@
+use clap::Parser;
+
+use rcarc::config::CliArgs;
+
+fn parses(args: &[&str]) -> bool {
+    CliArgs::try_parse_from(std::iter::once("rcarc").chain(args.iter().copied())).is_ok()
+}
+
+#[test]
+fn watch_interval_must_be_positive_and_not_overflow() {
+    assert!(parses(&["new", "--watch", "90s"]));
+    assert!(parses(&["new", "--watch", "6h"]));
+    assert!(!parses(&["new", "--watch", "0s"]));
+    assert!(!parses(&["new", "--watch", "0"]));
+    assert!(!parses(&["new", "--watch", "99999999999999999d"]));
+}
+
+#[test]
+fn command_is_optional_with_print_config_and_migrate_only() {
+    assert!(parses(&["--print-config"]));
+    assert!(parses(&["--migrate-only"]));
+    assert!(parses(&["--migrate-only", "stats"]));
+}
+
+#[test]
+fn verbose_can_be_turned_off() {
+    assert!(parses(&["--no-verbose", "stats"]));
+    assert!(!parses(&["--verbose", "--no-verbose", "stats"]));
+}
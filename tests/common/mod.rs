@@ -0,0 +1,262 @@
+// every test file includes this module, but none of them use all of it
+#![allow(dead_code)]
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use clap::Parser;
+use libfj::robocraft::{FactoryInfo, FactoryRobotGetInfo, FactoryRobotListInfo, RoboShopItemsInfo};
+
+use rcarc::client::{ApiResult, Connect, FactoryClient, Fetched, Search};
+use rcarc::config::CliArgs;
+
+/// A request made to the fake factory
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Call {
+    List { page: isize, page_size: isize },
+    Get(usize),
+}
+
+#[derive(Default)]
+struct Inner {
+    robots: BTreeMap<usize, FactoryRobotGetInfo>,
+    unlisted: BTreeSet<usize>,
+    get_failures: BTreeMap<usize, (u16, usize)>,
+    list_failure: Option<u16>,
+    delays: BTreeMap<usize, std::time::Duration>,
+    delaying: bool,
+    gets_while_delaying: usize,
+    calls: Vec<Call>,
+}
+
+/// In-memory factory, which can be told to fail in various ways.
+///
+/// Clones share the same robots and call log, so one can be given to every worker thread.
+#[derive(Clone, Default)]
+pub struct FakeFactory {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl FakeFactory {
+    /// A factory with robots `1..=count`, all of them searchable
+    pub fn with_robots(count: usize) -> Self {
+        let fake = Self::default();
+        for id in 1..=count {
+            fake.add_robot(id);
+        }
+        fake
+    }
+
+    pub fn add_robot(&self, id: usize) {
+        self.inner.lock().unwrap().robots.insert(id, robot(id));
+    }
+
+    pub fn remove_robot(&self, id: usize) {
+        self.inner.lock().unwrap().robots.remove(&id);
+    }
+
+    pub fn rename(&self, id: usize, name: &str) {
+        self.inner.lock().unwrap().robots.get_mut(&id).unwrap().item_name = name.to_owned();
+    }
+
+    pub fn set_dates(&self, id: usize, added_date: &str, expiry_date: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        let robot = inner.robots.get_mut(&id).unwrap();
+        robot.added_date = added_date.to_owned();
+        robot.expiry_date = expiry_date.to_owned();
+    }
+
+    /// The robot is non-buyable: it can be retrieved by ID, but only shows up in searches which include non-buyable robots
+    pub fn unlist(&self, id: usize) {
+        self.inner.lock().unwrap().unlisted.insert(id);
+    }
+
+    /// Respond to the next `times` requests for robot `id` with HTTP `status`
+    pub fn fail_get(&self, id: usize, status: u16, times: usize) {
+        self.inner.lock().unwrap().get_failures.insert(id, (status, times));
+    }
+
+    /// Take `delay` to respond to the next request for robot `id`
+    pub fn delay_get(&self, id: usize, delay: std::time::Duration) {
+        self.inner.lock().unwrap().delays.insert(id, delay);
+    }
+
+    /// Requests for other robots made while a delayed robot was being retrieved
+    pub fn gets_while_delaying(&self) -> usize {
+        self.inner.lock().unwrap().gets_while_delaying
+    }
+
+    /// Respond to every search with HTTP `status`
+    pub fn fail_list(&self, status: u16) {
+        self.inner.lock().unwrap().list_failure = Some(status);
+    }
+
+    /// Requests made so far
+    pub fn calls(&self) -> Vec<Call> {
+        self.inner.lock().unwrap().calls.clone()
+    }
+
+    pub fn clear_calls(&self) {
+        self.inner.lock().unwrap().calls.clear();
+    }
+
+    /// IDs of the robots which were requested individually, in order
+    pub fn gets(&self) -> Vec<usize> {
+        self.calls().into_iter()
+            .filter_map(|call| match call {
+                Call::Get(id) => Some(id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn connect(&self) -> Box<Connect> {
+        let fake = self.clone();
+        Box::new(move || Box::new(fake.clone()) as Box<dyn FactoryClient>)
+    }
+}
+
+impl FactoryClient for FakeFactory {
+    fn list(&self, search: &Search) -> ApiResult<RoboShopItemsInfo> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.calls.push(Call::List { page: search.page, page_size: search.page_size });
+        if let Some(status) = inner.list_failure {
+            return Err(status_error(status));
+        }
+        let items = inner.robots.values().rev()
+            .filter(|robot| !search.buyable_only || !inner.unlisted.contains(&robot.item_id))
+            .filter(|robot| search.player.as_ref().is_none_or(|player| robot.added_by == *player))
+            .skip((search.page * search.page_size) as usize)
+            .take(search.page_size as usize)
+            .map(list_info)
+            .collect();
+        Ok(fetched(FactoryInfo {
+            response: RoboShopItemsInfo { roboshop_items: items },
+            status_code: 200,
+        }))
+    }
+
+    fn get(&self, id: usize) -> ApiResult<FactoryRobotGetInfo> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.calls.push(Call::Get(id));
+        if inner.delaying {
+            inner.gets_while_delaying += 1;
+        }
+        if let Some(delay) = inner.delays.remove(&id) {
+            inner.delaying = true;
+            drop(inner);
+            std::thread::sleep(delay);
+            inner = self.inner.lock().unwrap();
+            inner.delaying = false;
+        }
+        if let Some((status, times)) = inner.get_failures.get_mut(&id) {
+            if *times > 0 {
+                *times -= 1;
+                return Err(status_error(*status));
+            }
+        }
+        match inner.robots.get(&id) {
+            Some(robot) => Ok(fetched(FactoryInfo {
+                response: robot.clone(),
+                status_code: 200,
+            })),
+            None => Err(status_error(404)),
+        }
+    }
+}
+
+fn fetched<T: serde::Serialize>(info: FactoryInfo<T>) -> Fetched<T> {
+    Fetched {
+        raw: serde_json::to_value(&info).unwrap(),
+        info,
+    }
+}
+
+fn status_error(status: u16) -> ureq::Error {
+    ureq::Error::Status(status, ureq::Response::new(status, "Fake Failure", "").unwrap())
+}
+
+pub fn robot(id: usize) -> FactoryRobotGetInfo {
+    FactoryRobotGetInfo {
+        item_id: id,
+        item_name: format!("Robot {}", id),
+        item_description: format!("Test robot #{}", id),
+        thumbnail: format!("https://example.com/thumbnails/{}.jpg", id),
+        added_by: format!("player{}", id % 3),
+        added_by_display_name: format!("Player {}", id % 3),
+        added_date: "2022-01-01T00:00:00".to_owned(),
+        expiry_date: "2023-01-01T00:00:00".to_owned(),
+        cpu: id % 2000,
+        total_robot_ranking: id * 10,
+        rent_count: 0,
+        buy_count: id % 7,
+        buyable: true,
+        removed_date: None,
+        ban_date: None,
+        featured: false,
+        banner_message: None,
+        combat_rating: 2.5,
+        cosmetic_rating: 3.5,
+        cube_data: format!("cubes{}", id),
+        colour_data: format!("colours{}", id),
+        cube_amounts: "{}".to_owned(),
+    }
+}
+
+fn list_info(robot: &FactoryRobotGetInfo) -> FactoryRobotListInfo {
+    FactoryRobotListInfo {
+        item_id: robot.item_id,
+        item_name: robot.item_name.clone(),
+        item_description: robot.item_description.clone(),
+        thumbnail: robot.thumbnail.clone(),
+        added_by: robot.added_by.clone(),
+        added_by_display_name: robot.added_by_display_name.clone(),
+        added_date: robot.added_date.clone(),
+        expiry_date: robot.expiry_date.clone(),
+        cpu: robot.cpu,
+        total_robot_ranking: robot.total_robot_ranking,
+        rent_count: robot.rent_count,
+        buy_count: robot.buy_count,
+        buyable: robot.buyable,
+        removed_date: robot.removed_date.clone(),
+        ban_date: robot.ban_date.clone(),
+        featured: robot.featured,
+        banner_message: robot.banner_message.clone(),
+        combat_rating: robot.combat_rating,
+        cosmetic_rating: robot.cosmetic_rating,
+        cube_amounts: robot.cube_amounts.clone(),
+    }
+}
+
+/// Path for a new (empty) database, unique to the test
+pub fn database(name: &str) -> PathBuf {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("{}.db", name));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+/// Parse command line arguments, with the database at `db` and no delay between retries
+pub fn args(db: &std::path::Path, args: &[&str]) -> CliArgs {
+    let mut full = vec!["rcarc", "--database", db.to_str().unwrap()];
+    full.extend_from_slice(args);
+    full.extend_from_slice(&["--retry-delay", "0"]);
+    CliArgs::try_parse_from(full).unwrap()
+}
+
+/// Run the command given by `cli` against the fake factory
+pub fn run(fake: &FakeFactory, db: &std::path::Path, cli: &[&str]) -> rcarc::error::Result<()> {
+    rcarc::run_with(&args(db, cli), fake.connect().as_ref())
+}
+
+/// Run a command which only uses the database
+pub fn run_local(db: &std::path::Path, cli: &[&str]) -> rcarc::error::Result<()> {
+    let mut full = vec!["rcarc", "--database", db.to_str().unwrap()];
+    full.extend_from_slice(cli);
+    rcarc::run_with(&CliArgs::try_parse_from(full).unwrap(), FakeFactory::default().connect().as_ref())
+}
+
+pub fn count(db: &std::path::Path, query: &str) -> usize {
+    let db = rusqlite::Connection::open(db).unwrap();
+    db.query_row(query, [], |row| row.get(0)).unwrap()
+}
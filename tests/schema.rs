@@ -0,0 +1,55 @@
+mod common;
+
+use common::count;
+
+#[test]
+fn new_database_is_at_latest_version() {
+    let db = common::database("schema_new");
+    common::run_local(&db, &["stats"]).unwrap();
+    assert!(count(&db, "PRAGMA user_version;") >= 1);
+}
+
+#[test]
+fn dry_run_changes_nothing() {
+    let db = common::database("schema_dry_run");
+    common::run_local(&db, &["--migrate-only"]).unwrap();
+    assert_eq!(count(&db, "PRAGMA user_version;"), 0);
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM sqlite_master;"), 0);
+}
+
+#[test]
+fn unversioned_database_is_upgraded() {
+    let db = common::database("schema_unversioned");
+    {
+        // as created before migrations were tracked, with only some of the tables
+        let conn = rusqlite::Connection::open(&db).unwrap();
+        conn.execute_batch("
+            CREATE TABLE ROBOT_METADATA (
+                id INTEGER NOT NULL PRIMARY KEY, name TEXT NOT NULL, description TEXT NOT NULL, thumbnail TEXT NOT NULL,
+                added_by TEXT NOT NULL, added_by_display_name TEXT NOT NULL, added_date TEXT NOT NULL, expiry_date TEXT NOT NULL,
+                cpu INTEGER NOT NULL, total_robot_ranking INTEGER NOT NULL, rent_count INTEGER NOT NULL, buy_count INTEGER NOT NULL,
+                buyable INTEGER NOT NULL, featured INTEGER NOT NULL, combat_rating REAL NOT NULL, cosmetic_rating REAL NOT NULL
+            );
+            INSERT INTO ROBOT_METADATA VALUES (1, 'Robot 1', '', '', 'player1', 'Player 1', '2022-01-01T00:00:00', '2023-01-01T00:00:00', 100, 0, 0, 0, 1, 0, 0.0, 0.0);
+        ").unwrap();
+    }
+    common::run_local(&db, &["stats"]).unwrap();
+    assert!(count(&db, "PRAGMA user_version;") >= 1);
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM ROBOT_METADATA;"), 1);
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM ROBOT_METADATA_HISTORY WHERE observed_at = 0;"), 1);
+    // 2022-01-01 and 2023-01-01
+    assert_eq!(count(&db, "SELECT added_at FROM ROBOT_METADATA WHERE id = 1;"), 1640995200);
+    assert_eq!(count(&db, "SELECT expires_at FROM ROBOT_METADATA WHERE id = 1;"), 1672531200);
+}
+
+#[test]
+fn newer_database_is_refused() {
+    let db = common::database("schema_newer");
+    {
+        let conn = rusqlite::Connection::open(&db).unwrap();
+        conn.execute_batch("PRAGMA user_version = 9999;").unwrap();
+    }
+    let err = common::run_local(&db, &["stats"]).unwrap_err();
+    assert_eq!(err.exit_code(), 9);
+    assert_eq!(count(&db, "SELECT COUNT(*) FROM sqlite_master;"), 0);
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+
+use libfj::robocraft::FactoryInfo;
+
+use rcarc::auth::{Credentials, Login, Session};
+use rcarc::client::{FactoryClient, HttpFactory};
+
+/// Requests which reached the fake server, as (method and path, Authorization header)
+type Log = Arc<Mutex<Vec<(String, String)>>>;
+
+/// Account and factory server on localhost, which hands out `token1`, `token2`, etc. and only accepts `accepted`
+fn serve(accepted: &'static str) -> (String, Log) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let log = Log::default();
+    let server_log = log.clone();
+    std::thread::spawn(move || {
+        let mut logins = 0;
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let (mut authorization, mut length) = (String::new(), 0);
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                let header = header.trim_end();
+                if header.is_empty() {
+                    break;
+                }
+                let (name, value) = header.split_once(": ").unwrap();
+                match name.to_ascii_lowercase().as_str() {
+                    "authorization" => authorization = value.to_owned(),
+                    "content-length" => length = value.parse().unwrap(),
+                    _ => {},
+                }
+            }
+            reader.read_exact(&mut vec![0; length]).unwrap();
+            let request = request_line.split(' ').take(2).collect::<Vec<_>>().join(" ");
+            server_log.lock().unwrap().push((request.clone(), authorization.clone()));
+            let (status, body) = if request.starts_with("POST /api/authenticate/") {
+                logins += 1;
+                (200, format!(r#"{{"Token":"token{}"}}"#, logins))
+            } else if authorization != format!("Web {}", accepted) {
+                (401, String::new())
+            } else if request.ends_with("/get/2") {
+                (200, "{\"response\": garbage".to_owned())
+            } else {
+                let info = FactoryInfo { response: common::robot(1), status_code: 200 };
+                (200, serde_json::to_string(&info).unwrap())
+            };
+            write!(stream, "HTTP/1.1 {} Fake\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, body.len(), body).unwrap();
+        }
+    });
+    (url, log)
+}
+
+fn client(url: &str, credentials: Credentials) -> HttpFactory {
+    let agent = ureq::Agent::new();
+    let session = Session::new(agent.clone(), url, credentials);
+    session.login().unwrap();
+    HttpFactory::new(agent, url, Arc::new(session))
+}
+
+fn account() -> Credentials {
+    Credentials::Account { login: Login::DisplayName("player".to_owned()), password: "hunter2".to_owned() }
+}
+
+#[test]
+fn rejected_token_is_renewed_once() {
+    let (url, log) = serve("token2");
+    let api = client(&url, account());
+    assert_eq!(api.get(1).unwrap().info.response.item_id, 1);
+    assert_eq!(api.get(1).unwrap().info.response.item_id, 1);
+
+    let log = log.lock().unwrap();
+    let requests: Vec<(&str, &str)> = log.iter().map(|(request, auth)| (request.as_str(), auth.as_str())).collect();
+    assert_eq!(requests, [
+        ("POST /api/authenticate/displayname/web", ""),
+        ("GET /api/roboShopItems/get/1", "Web token1"),
+        ("POST /api/authenticate/displayname/web", ""),
+        ("GET /api/roboShopItems/get/1", "Web token2"),
+        // the new token is kept
+        ("GET /api/roboShopItems/get/1", "Web token2"),
+    ]);
+}
+
+#[test]
+fn token_is_only_renewed_once_per_request() {
+    let (url, log) = serve("never");
+    let api = client(&url, account());
+    match api.get(1) {
+        Err(ureq::Error::Status(401, _)) => {},
+        other => panic!("expected 401, got {:?}", other.map(|fetched| fetched.info.status_code)),
+    }
+    assert_eq!(log.lock().unwrap().len(), 4);
+}
+
+#[test]
+fn token_file_is_not_renewed() {
+    let (url, log) = serve("token1");
+    let api = client(&url, Credentials::Token("stale".to_owned()));
+    assert!(matches!(api.get(1), Err(ureq::Error::Status(401, _))));
+    let log = log.lock().unwrap();
+    assert_eq!(log.len(), 1);
+    assert_eq!(log[0].1, "Web stale");
+}
+
+#[test]
+fn malformed_response_is_an_api_error() {
+    let (url, _) = serve("stale");
+    let api = client(&url, Credentials::Token("stale".to_owned()));
+    let err = api.get(2).err().expect("malformed response");
+    assert!(matches!(err, ureq::Error::Transport(_)));
+    assert_eq!(rcarc::error::Error::from(err).exit_code(), 4);
+}